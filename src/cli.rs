@@ -0,0 +1,449 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use k8s_openapi::api::authentication::v1::UserInfo;
+use k8s_openapi::api::core::v1::Pod;
+
+use crate::config::{self, Config, Mode};
+use crate::error::ConfigError;
+use crate::handler::{self, Mutation, Verdict};
+use crate::server;
+use crate::utils::patch::PodPaths;
+
+/// A simple kubernetes utility to make pods in specific namespaces run in specific nodes
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum Command {
+	/// Runs the webhook server, this is the default when no command is given
+	Serve,
+	/// Loads and validates a configuration file, exiting with a non-zero code if it's invalid
+	ValidateConfig {
+		/// Path to the configuration file
+		file: PathBuf,
+	},
+	/// Prints the default configuration as commented YAML
+	PrintDefaultConfig,
+	/// Simulates the admission of a pod without a cluster, printing the patches, the mutated pod or the denial reason
+	Simulate {
+		/// Path to the configuration file
+		#[arg(short, long)]
+		config: PathBuf,
+		/// Name of the group the pod's namespace belongs to
+		#[arg(short, long)]
+		group: String,
+		/// Annotation of the pod's namespace, such as the group's allowed overrides, may be repeated
+		#[arg(short, long = "namespace-annotation", value_name = "KEY=VALUE", value_parser = parse_annotation)]
+		namespace_annotations: Vec<(String, String)>,
+		/// What to print when the pod is allowed
+		#[arg(short, long, value_enum, default_value_t = SimulateOutput::Patch)]
+		output: SimulateOutput,
+		/// Path to the Pod manifest, either YAML or JSON
+		pod: PathBuf,
+	},
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SimulateOutput {
+	/// The JSON patch that would be returned to the API server
+	Patch,
+	/// The pod after applying the patch
+	Pod,
+}
+
+impl Command {
+	pub async fn run(self) -> ExitCode {
+		match self {
+			Command::Serve => serve().await,
+			Command::ValidateConfig { file } => match validate_config(&file) {
+				Ok(_) => {
+					println!("Configuration file \"{}\" is valid", file.display());
+					ExitCode::SUCCESS
+				}
+				Err(report) => {
+					eprintln!("{report}");
+					ExitCode::FAILURE
+				}
+			},
+			Command::PrintDefaultConfig => {
+				print!("{}", config::default_yaml());
+				ExitCode::SUCCESS
+			}
+			Command::Simulate { config, group, namespace_annotations, output, pod } => {
+				let namespace_annotations = namespace_annotations.into_iter().collect();
+				match simulate(&config, &group, &namespace_annotations, &pod, output) {
+					Ok(result) => {
+						println!("{result}");
+						ExitCode::SUCCESS
+					}
+					Err(report) => {
+						eprintln!("{report}");
+						ExitCode::FAILURE
+					}
+				}
+			}
+		}
+	}
+}
+
+async fn serve() -> ExitCode {
+	let display = Config::config_file().display().to_string();
+	let config = match Config::load() {
+		Ok(config) => config,
+		Err(e) => {
+			eprintln!("Failed loading configuration file \"{display}\": {e}");
+			return ExitCode::FAILURE;
+		}
+	};
+
+	let errors = config.validate();
+	if !errors.is_empty() {
+		eprintln!("{}", invalid_report(&display, &errors));
+		return ExitCode::FAILURE;
+	}
+
+	let config = Arc::new(config);
+	println!("Loaded config:");
+	println!("{config:?}");
+
+	match server::serve(config).await {
+		Ok(_) => ExitCode::SUCCESS,
+		Err(e) => {
+			println!("ERROR: Failed serving server: {e}");
+			ExitCode::FAILURE
+		}
+	}
+}
+
+fn validate_config(file: &Path) -> Result<Config, String> {
+	let display = file.display();
+
+	if !file.is_file() {
+		return Err(format!("Configuration file \"{display}\" does not exist"));
+	}
+
+	let config = Config::load_file(file)
+		.map_err(|e| format!("Failed loading configuration file \"{display}\": {e}"))?;
+
+	let errors = config.validate();
+	if !errors.is_empty() {
+		return Err(invalid_report(&display, &errors));
+	}
+
+	Ok(config)
+}
+
+fn parse_annotation(annotation: &str) -> Result<(String, String), String> {
+	annotation
+		.split_once('=')
+		.map(|(key, value)| (key.to_string(), value.to_string()))
+		.ok_or_else(|| format!("\"{annotation}\" must be in the form KEY=VALUE"))
+}
+
+fn invalid_report<D: std::fmt::Display>(display: D, errors: &[ConfigError]) -> String {
+	let report: Vec<String> = errors.iter().map(|e| format!("  - {e}")).collect();
+	format!("Configuration file \"{display}\" is invalid:\n{}", report.join("\n"))
+}
+
+fn simulate(
+	config_file: &Path,
+	group: &str,
+	namespace_annotations: &BTreeMap<String, String>,
+	pod_file: &Path,
+	output: SimulateOutput,
+) -> Result<String, String> {
+	let config = validate_config(config_file)?;
+
+	let manifest = std::fs::read_to_string(pod_file)
+		.map_err(|e| format!("Failed reading pod manifest \"{}\": {e}", pod_file.display()))?;
+	let pod: Pod = serde_yaml::from_str(&manifest)
+		.map_err(|e| format!("Failed parsing pod manifest \"{}\": {e}", pod_file.display()))?;
+
+	// Without a cluster the pod is treated as requested by an unknown user
+	let document = serde_json::to_value(&pod).unwrap();
+	let verdict = handler::decide(&config, group, namespace_annotations, &document, &PodPaths::root(), &UserInfo::default(), &mut Vec::new());
+
+	let patches = match verdict {
+		Verdict::NoPodSpec => return Err(format!("Pod manifest \"{}\" has no spec", pod_file.display())),
+		Verdict::MissingGroupConfig => return Err(format!("No pod-director group configured with the name {group}")),
+		Verdict::Exempt(reason) => {
+			eprintln!("{reason}, the pod would be allowed unchanged");
+			Vec::new()
+		}
+		Verdict::Mutate { mode, mutation, .. } => {
+			if mode == Mode::DryRun {
+				eprintln!("Group {group} is in dry run mode, the pod would be allowed unchanged with warnings instead");
+			}
+
+			match mutation {
+				Mutation::Patch(patches) => patches,
+				Mutation::Deny(reason) => return Err(format!("Pod would be denied: {reason}")),
+			}
+		}
+	};
+	let patch = json_patch::Patch(patches);
+
+	match output {
+		SimulateOutput::Patch => Ok(serde_json::to_string_pretty(&patch).unwrap()),
+		SimulateOutput::Pod => {
+			let mut mutated = document;
+			json_patch::patch(&mut mutated, &patch).map_err(|e| format!("Failed applying patch to the pod: {e}"))?;
+			Ok(serde_json::to_string_pretty(&mutated).unwrap())
+		}
+	}
+}
+
+#[cfg(test)]
+#[allow(clippy::result_large_err)]
+mod tests {
+	use std::collections::BTreeMap;
+	use std::path::Path;
+
+	use clap::Parser;
+	use figment::Jail;
+	use indoc::indoc;
+
+	use serde_json::{json, Value};
+
+	use super::{Cli, Command, simulate, SimulateOutput, validate_config};
+
+	static SIMULATE_CONFIG: &str = indoc! { r#"
+		groups:
+		  foo:
+		    nodeSelector:
+		      role: foo
+		    onConflict: Reject
+	"# };
+
+	static SIMULATE_POD: &str = indoc! { r#"
+		apiVersion: v1
+		kind: Pod
+		metadata:
+		  name: test
+		spec:
+		  containers:
+		    - name: test
+		      image: alpine
+	"# };
+
+	#[test]
+	fn given_no_command_then_should_not_parse_any_command() {
+		let cli = Cli::try_parse_from(["pod-director"]).unwrap();
+
+		assert_eq!(cli.command, None);
+	}
+
+	#[test]
+	fn given_validate_config_command_then_should_parse_file() {
+		let cli = Cli::try_parse_from(["pod-director", "validate-config", "some-file.yaml"]).unwrap();
+
+		assert_eq!(cli.command, Some(Command::ValidateConfig { file: "some-file.yaml".into() }));
+	}
+
+	#[test]
+	fn given_simulate_command_with_namespace_annotations_then_should_parse_them() {
+		let cli = Cli::try_parse_from([
+			"pod-director", "simulate", "--config", "config.yaml", "--group", "foo",
+			"--namespace-annotation", "pod-director/nodeSelector={role: bar}", "-n", "example.com/owner=team-a=b", "pod.yaml",
+		]).unwrap();
+
+		assert_eq!(cli.command, Some(Command::Simulate {
+			config: "config.yaml".into(),
+			group: "foo".into(),
+			namespace_annotations: vec![
+				("pod-director/nodeSelector".into(), "{role: bar}".into()),
+				("example.com/owner".into(), "team-a=b".into()),
+			],
+			output: SimulateOutput::Patch,
+			pod: "pod.yaml".into(),
+		}));
+	}
+
+	#[test]
+	fn given_namespace_annotation_without_value_then_should_fail_parsing() {
+		let result = Cli::try_parse_from([
+			"pod-director", "simulate", "--config", "config.yaml", "--group", "foo", "--namespace-annotation", "owner", "pod.yaml",
+		]);
+
+		assert!(result.is_err());
+	}
+
+	#[test]
+	fn given_valid_config_file_then_validation_should_succeed() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", indoc! { r#"
+				groups:
+				  foo:
+				    nodeSelector:
+				      kubernetes.io/os: linux
+			"# })?;
+
+			let config = validate_config(Path::new("config.yaml")).unwrap();
+
+			assert!(config.groups.contains_key("foo"));
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_invalid_config_file_then_validation_should_report_errors() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", indoc! { r#"
+				groupLabel: "not-valid-@-label"
+				groups:
+				  foo:
+				    tolerations:
+				      - key: foo
+				        operator: Equals
+			"# })?;
+
+			assert_eq!(validate_config(Path::new("config.yaml")).unwrap_err(), indoc! { r#"
+				Configuration file "config.yaml" is invalid:
+				  - invalid value for "groupLabel": "not-valid-@-label" is not a valid label key
+				  - invalid value for "groups.foo.tolerations[0].operator": "Equals" is not a valid operator, must be either "Equal" or "Exists""#
+			});
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_unparseable_config_file_then_validation_should_fail() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", "groups: []")?;
+
+			assert!(validate_config(Path::new("config.yaml")).unwrap_err().starts_with(r#"Failed loading configuration file "config.yaml""#));
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_missing_config_file_then_validation_should_fail() {
+		assert_eq!(
+			validate_config(Path::new("does-not-exist.yaml")).unwrap_err(),
+			r#"Configuration file "does-not-exist.yaml" does not exist"#
+		);
+	}
+
+	#[test]
+	fn given_simulate_with_patch_output_then_should_print_patch() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", SIMULATE_CONFIG)?;
+			jail.create_file("pod.yaml", SIMULATE_POD)?;
+
+			let result = simulate(Path::new("config.yaml"), "foo", &BTreeMap::new(), Path::new("pod.yaml"), SimulateOutput::Patch).unwrap();
+
+			assert_eq!(serde_json::from_str::<Value>(&result).unwrap(), json!([
+				{"op": "add", "path": "/spec/nodeSelector", "value": {"role": "foo"}},
+				{"op": "add", "path": "/metadata/annotations", "value": {
+					"pod-director/applied-node-selector": "role=foo",
+					"pod-director/group": "foo",
+				}},
+			]));
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_simulate_with_namespace_override_then_should_apply_it() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", &format!("{SIMULATE_CONFIG}    allowNamespaceOverrides: [nodeSelector]\n"))?;
+			jail.create_file("pod.yaml", SIMULATE_POD)?;
+			let annotations = BTreeMap::from([("pod-director/nodeSelector".into(), "{zone: a}".into())]);
+
+			let result = simulate(Path::new("config.yaml"), "foo", &annotations, Path::new("pod.yaml"), SimulateOutput::Pod).unwrap();
+			let pod = serde_json::from_str::<Value>(&result).unwrap();
+
+			assert_eq!(pod["spec"]["nodeSelector"], json!({"role": "foo", "zone": "a"}));
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_simulate_with_pod_output_then_should_print_mutated_pod() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", SIMULATE_CONFIG)?;
+			jail.create_file("pod.json", &serde_json::to_string(&serde_yaml::from_str::<Value>(SIMULATE_POD).unwrap()).unwrap())?;
+
+			let result = simulate(Path::new("config.yaml"), "foo", &BTreeMap::new(), Path::new("pod.json"), SimulateOutput::Pod).unwrap();
+			let pod = serde_json::from_str::<Value>(&result).unwrap();
+
+			assert_eq!(pod["spec"]["nodeSelector"], json!({"role": "foo"}));
+			assert_eq!(pod["metadata"]["annotations"]["pod-director/group"], json!("foo"));
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_simulate_with_conflicting_pod_then_should_report_denial() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", SIMULATE_CONFIG)?;
+			jail.create_file("pod.yaml", &format!("{SIMULATE_POD}  nodeSelector:\n    role: other\n"))?;
+
+			let result = simulate(Path::new("config.yaml"), "foo", &BTreeMap::new(), Path::new("pod.yaml"), SimulateOutput::Patch);
+
+			assert_eq!(
+				result,
+				Err("Pod would be denied: The pod's nodeSelector role=other conflicts with pod-director's configuration role=foo".into())
+			);
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_simulate_with_daemon_set_pod_then_should_print_empty_patch() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", SIMULATE_CONFIG)?;
+			jail.create_file("pod.yaml", indoc! { r#"
+				apiVersion: v1
+				kind: Pod
+				metadata:
+				  name: test
+				  ownerReferences:
+				    - apiVersion: apps/v1
+				      kind: DaemonSet
+				      name: node-exporter
+				      uid: 5b9f3c9e-0b7a-4c1e-9a57-3c1f0d8a2e41
+				      controller: true
+				spec:
+				  containers:
+				    - name: test
+				      image: alpine
+			"# })?;
+
+			let result = simulate(Path::new("config.yaml"), "foo", &BTreeMap::new(), Path::new("pod.yaml"), SimulateOutput::Patch).unwrap();
+
+			assert_eq!(serde_json::from_str::<Value>(&result).unwrap(), json!([]));
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_simulate_with_unknown_group_then_should_fail() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", SIMULATE_CONFIG)?;
+			jail.create_file("pod.yaml", SIMULATE_POD)?;
+
+			let result = simulate(Path::new("config.yaml"), "bar", &BTreeMap::new(), Path::new("pod.yaml"), SimulateOutput::Patch);
+
+			assert_eq!(result, Err("No pod-director group configured with the name bar".into()));
+
+			Ok(())
+		});
+	}
+}
@@ -1,32 +1,118 @@
-use std::collections::HashMap;
+// figment's error type is large, but it's only ever returned once while loading
+#![allow(clippy::result_large_err)]
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::net::{IpAddr, SocketAddr};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{Error, Result};
 use axum_server::tls_rustls::RustlsConfig;
 use figment::{error, Figment, providers::{Env, Format, Yaml}};
 use figment::providers::Serialized;
-use k8s_openapi::api::core::v1::Toleration;
-use serde::{Deserialize, Serialize};
+use k8s_openapi::api::authentication::v1::UserInfo;
+use k8s_openapi::api::core::v1::{EnvVar, Pod, Toleration, TopologySpreadConstraint, Volume, VolumeMount};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::error::ConfigError;
+use crate::utils::{image, label, quantity};
+use crate::utils::patch::{PodPaths, Pointer};
+
+static ENV_PREFIX: &str = "PD_";
+static ENV_CONFIG_FILE: &str = "PD_CONFIG_FILE";
+static DEFAULT_CONFIG_FILE: &str = "pd-config.yaml";
+/// Namespace annotations overriding a group's field are named with this prefix followed by the field's name
+static NAMESPACE_OVERRIDE_PREFIX: &str = "pod-director/";
+/// Fields namespaces may override, if allowed by their group, others could let namespaces escape their group's restrictions
+///
+/// Each is paired with how an annotation's value is parsed into a group configuration holding only the overrides.
+static NAMESPACE_OVERRIDABLE_FIELDS: [(&str, OverrideParser); 8] = [
+	("nodeSelector", |o, v| serde_yaml::from_str(v).map(|value| o.node_selector = Some(value))),
+	("tolerations", |o, v| serde_yaml::from_str(v).map(|value| o.tolerations = Some(value))),
+	("topologySpreadConstraints", |o, v| serde_yaml::from_str(v).map(|value| o.topology_spread_constraints = Some(value))),
+	("priorityClassName", |o, v| serde_yaml::from_str(v).map(|value| o.priority_class_name = Some(value))),
+	("schedulerName", |o, v| serde_yaml::from_str(v).map(|value| o.scheduler_name = Some(value))),
+	("runtimeClassName", |o, v| serde_yaml::from_str(v).map(|value| o.runtime_class_name = Some(value))),
+	("podLabels", |o, v| serde_yaml::from_str(v).map(|value| o.pod_labels = Some(value))),
+	("podAnnotations", |o, v| serde_yaml::from_str(v).map(|value| o.pod_annotations = Some(value))),
+];
+
+type OverrideParser = fn(&mut GroupConfig, &str) -> serde_yaml::Result<()>;
 
-static ENV_PREFIX: &'static str = "PD_";
-static ENV_CONFIG_FILE: &'static str = "PD_CONFIG_FILE";
-static DEFAULT_CONFIG_FILE: &'static str = "pd-config.yaml";
+fn is_namespace_overridable(field: &str) -> bool {
+	NAMESPACE_OVERRIDABLE_FIELDS.iter().any(|(name, _)| *name == field)
+}
 
 impl Config {
 	pub fn load() -> error::Result<Self> {
-		let config_file = std::env::var(ENV_CONFIG_FILE).unwrap_or(DEFAULT_CONFIG_FILE.into());
-
-		Figment::from(Serialized::defaults(Config::default()))
-			.merge(Yaml::file(config_file))
+		Self::file_figment(Self::config_file())
 			.merge(Env::prefixed(ENV_PREFIX).split("_"))
 			.extract()
 	}
+
+	/// Loads only the file over the defaults, ignoring the environment, so the result is the same wherever it's loaded
+	pub fn load_file<P: AsRef<Path>>(config_file: P) -> error::Result<Self> {
+		Self::file_figment(config_file).extract()
+	}
+
+	/// The file read by `load`, which may be changed through the environment
+	pub fn config_file() -> PathBuf {
+		std::env::var(ENV_CONFIG_FILE).unwrap_or(DEFAULT_CONFIG_FILE.into()).into()
+	}
+
+	fn file_figment<P: AsRef<Path>>(config_file: P) -> Figment {
+		Figment::from(Serialized::defaults(Config::default()))
+			.merge(Yaml::file(config_file.as_ref()))
+	}
+
+	/// Checks the loaded configuration for values that would be rejected by Kubernetes or never match anything
+	pub fn validate(&self) -> Vec<ConfigError> {
+		let mut errors = Vec::new();
+
+		if !label::is_valid_key(&self.group_label) {
+			errors.push(invalid("groupLabel", format!("\"{}\" is not a valid label key", self.group_label)));
+		}
+
+		errors.extend(self.exemptions.validate("exemptions"));
+
+		let mut resources = Vec::new();
+		for (i, resource) in self.custom_resources.iter().enumerate() {
+			let field = format!("customResources[{i}]");
+			errors.extend(resource.validate(&field));
+
+			if resources.contains(&(&resource.group, &resource.kind)) {
+				errors.push(invalid(
+					format!("{field}.kind"),
+					format!("\"{}\" in group \"{}\" is configured more than once", resource.kind, resource.group),
+				));
+			}
+			resources.push((&resource.group, &resource.kind));
+		}
+
+		for (name, group_config) in &self.groups {
+			let field = format!("groups.{name}");
+			if name.is_empty() || !label::is_valid_value(name) {
+				errors.push(invalid(&field, format!("\"{name}\" is not a valid label value and thus cannot be a group name")));
+			}
+			errors.extend(group_config.validate(&field));
+		}
+
+		errors
+	}
+
+	/// The mode a group runs in, falling back to the global mode when the group doesn't set one
+	pub fn group_mode(&self, group_config: &GroupConfig) -> Mode {
+		group_config.mode.unwrap_or(self.mode)
+	}
 }
 
-#[derive(Deserialize, Serialize, Default, Debug, PartialEq)]
+fn invalid<S: AsRef<str>>(field: S, reason: String) -> ConfigError {
+	ConfigError::Invalid { field: field.as_ref().to_string(), reason }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone, Copy)]
 pub enum Conflict {
 	Ignore,
 	Override,
@@ -34,13 +120,38 @@ pub enum Conflict {
 	Reject,
 }
 
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum Mode {
+	#[default]
+	Enforce,
+	/// Calculates everything as usual, but only warns about the changes instead of applying them
+	DryRun,
+}
+
+impl Mode {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Mode::Enforce => "enforce",
+			Mode::DryRun => "dryRun",
+		}
+	}
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 #[cfg_attr(test, derive(PartialEq))]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
-	pub groups: HashMap<String, GroupConfig>,
+	pub groups: BTreeMap<String, GroupConfig>,
 	pub group_label: String,
+	pub mode: Mode,
+	pub annotate_pods: bool,
+	/// Allows pods controlled by a DaemonSet unchanged, since they must be able to run on every node
+	pub skip_daemon_sets: bool,
+	pub exemptions: ExemptionsConfig,
+	pub custom_resources: Vec<CustomResourceConfig>,
 	pub server: ServerConfig,
+	pub health: HealthConfig,
 }
 
 impl Default for Config {
@@ -48,17 +159,187 @@ impl Default for Config {
 		Self {
 			groups: Default::default(),
 			group_label: "pod-director/group".to_string(),
+			mode: Default::default(),
+			annotate_pods: true,
+			skip_daemon_sets: true,
+			exemptions: Default::default(),
+			custom_resources: Default::default(),
 			server: Default::default(),
+			health: Default::default(),
 		}
 	}
 }
 
+/// Renders the default configuration as YAML, documenting every option
+pub fn default_yaml() -> String {
+	let config = Config::default();
+	let server = &config.server;
+	let health = &config.health;
+
+	format!(r#"# Groups of configurations applied to pods, keyed by group name
+# Namespaces are assigned to a group by setting the group label below to the group's name
+groups: {{}}
+#  cicd:
+#    # Labels added to the pod's nodeSelector
+#    nodeSelector:
+#      role: cicd
+#    # Tolerations added to the pod, unless it already has an equal toleration
+#    tolerations:
+#      - key: role
+#        operator: Equal
+#        value: cicd
+#        effect: NoSchedule
+#    # Topology spread constraints added to the pod, unless it already has one for the same topology key
+#    topologySpreadConstraints:
+#      - topologyKey: topology.kubernetes.io/zone
+#        maxSkew: 1
+#        whenUnsatisfiable: ScheduleAnyway
+#        labelSelector:
+#          matchExpressions:
+#            - key: app.kubernetes.io/name
+#              operator: Exists
+#        matchLabelKeys:
+#          - app.kubernetes.io/name
+#    # Set on the pod, the API server's "default-scheduler" is treated as no scheduler being set
+#    priorityClassName: cicd
+#    schedulerName: cicd-scheduler
+#    runtimeClassName: gvisor
+#    # Labels and annotations added to the pod's metadata
+#    podLabels:
+#      cost-center: cicd
+#    podAnnotations:
+#      cluster-autoscaler.kubernetes.io/safe-to-evict: "false"
+#    # Resources for every container and init container, existing requests and limits are never changed
+#    resources:
+#      defaultRequests:
+#        cpu: 100m
+#        memory: 128Mi
+#      defaultLimits:
+#        memory: 256Mi
+#      # Pods requesting more than this in total are denied
+#      maxRequests:
+#        cpu: "4"
+#        memory: 8Gi
+#    # Environment variables, volume mounts and volumes added to containers and init containers, optionally only those
+#    # with the given names
+#    injections:
+#      - containers: [app]
+#        env:
+#          - name: NVIDIA_VISIBLE_DEVICES
+#            value: all
+#        volumeMounts:
+#          - name: nvidia-driver
+#            mountPath: /usr/local/nvidia
+#            readOnly: true
+#        volumes:
+#          - name: nvidia-driver
+#            hostPath:
+#              path: /home/kubernetes/bin/nvidia
+#    # Pods with any container image not matching these patterns, where "*" matches anything, are denied
+#    # Patterns also match the image's normalized form, such as "docker.io/library/alpine" for "alpine"
+#    images:
+#      allowed:
+#        - registry.example.com/*
+#      denied:
+#        - "*-amd64"
+#    # Pods matching any of these are allowed unchanged, in addition to the global exemptions below
+#    exemptions:
+#      serviceAccounts:
+#        - gitlab-runner
+#    # Fields namespaces may extend or override by setting annotations named "pod-director/" followed by the field, such as
+#    # "pod-director/tolerations", to its value as YAML. Maps are extended, tolerations appended, topology spread
+#    # constraints replaced per topology key and anything else replaced. Only the scheduling and metadata fields above,
+#    # nodeSelector to podAnnotations, may be allowed
+#    allowNamespaceOverrides: [tolerations]
+#    # What to do when the pod already has a different value: Ignore, Override or Reject
+#    onConflict: Reject
+#    # Overrides the global mode for this group only
+#    mode: enforce
+
+# Pods matching any of these are allowed unchanged, groups may also set their own exemptions with the same options
+exemptions: {{}}
+#  podLabels:
+#    pod-director/exempt: "true"
+#  serviceAccounts:
+#    - cluster-autoscaler
+#  # Static pods are owned by their Node
+#  ownerKinds:
+#    - DaemonSet
+#    - Node
+#  users:
+#    - system:kube-scheduler
+#  userGroups:
+#    - system:nodes
+
+# Custom resources embedding a pod spec, mutated along with Deployments, StatefulSets, Jobs and CronJobs by the
+# "/mutate-workloads" webhook
+customResources: []
+#  - group: argoproj.io
+#    kind: Rollout
+#    # JSON pointers to the embedded pod's spec and metadata, labels and annotations are never set without the latter
+#    podSpecPath: /spec/template/spec
+#    podMetadataPath: /spec/template/metadata
+#  - group: tekton.dev
+#    kind: TaskRun
+#    podSpecPath: /spec/podTemplate
+
+# Label that must be assigned to namespaces for their pods to be directed, its value is the group's name
+groupLabel: {group_label}
+
+# Either "enforce", to patch pods, or "dryRun", to only warn and report metrics about what would be changed
+mode: {mode}
+
+# Annotates mutated pods with pod-director's decisions, making them visible with "kubectl describe"
+annotatePods: {annotate_pods}
+
+# Allows pods controlled by a DaemonSet unchanged, since a nodeSelector or similar would stop them from running on every node
+skipDaemonSets: {skip_daemon_sets}
+
+server:
+  # Address and port for the webhook, served over HTTPS unless insecure is set
+  bindAddr: {bind_addr}
+  port: {port}
+  # Address and port for the plain HTTP listener serving health checks and metrics
+  opsBindAddr: {ops_bind_addr}
+  opsPort: {ops_port}
+  # Serves the webhook over plain HTTP, Kubernetes only calls HTTPS webhooks so this is only useful for development
+  insecure: {insecure}
+  # Certificate and key for the webhook, both are reloaded automatically when changed
+  cert: {cert}
+  key: {key}
+
+health:
+  # How long the namespace watcher may keep failing before the pod is reported as not ready
+  watcherGracePeriodSeconds: {watcher_grace_period_seconds}
+"#,
+		group_label = yaml(&config.group_label),
+		mode = yaml(&config.mode),
+		annotate_pods = yaml(&config.annotate_pods),
+		skip_daemon_sets = yaml(&config.skip_daemon_sets),
+		bind_addr = yaml(&server.bind_addr),
+		port = yaml(&server.port),
+		ops_bind_addr = yaml(&server.ops_bind_addr),
+		ops_port = yaml(&server.ops_port),
+		insecure = yaml(&server.insecure),
+		cert = yaml(&server.cert),
+		key = yaml(&server.key),
+		watcher_grace_period_seconds = yaml(&health.watcher_grace_period_seconds),
+	)
+}
+
+/// JSON scalars are valid YAML and are always quoted correctly
+fn yaml<T: Serialize>(value: &T) -> String {
+	serde_json::to_string(value).expect("Config values are always serializable")
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 #[cfg_attr(test, derive(PartialEq))]
 #[serde(rename_all = "camelCase")]
 pub struct ServerConfig {
 	bind_addr: IpAddr,
 	port: u16,
+	ops_bind_addr: IpAddr,
+	ops_port: u16,
 	pub insecure: bool,
 	pub cert: PathBuf,
 	pub key: PathBuf,
@@ -69,6 +350,11 @@ impl ServerConfig {
 		SocketAddr::new(self.bind_addr, self.port)
 	}
 
+	/// Address for the plain HTTP listener serving operational routes, such as health checks
+	pub fn ops_socket_addr(&self) -> SocketAddr {
+		SocketAddr::new(self.ops_bind_addr, self.ops_port)
+	}
+
 	pub async fn tls_config(&self) -> Result<RustlsConfig> {
 		match RustlsConfig::from_pem_file(&self.cert, &self.key).await {
 			Ok(v) => Ok(v),
@@ -88,6 +374,8 @@ impl Default for ServerConfig {
 		ServerConfig {
 			bind_addr: IpAddr::from([0, 0, 0, 0]),
 			port: 8443,
+			ops_bind_addr: IpAddr::from([0, 0, 0, 0]),
+			ops_port: 8080,
 			insecure: false,
 			cert: PathBuf::from("certs/tls.crt"),
 			key: PathBuf::from("certs/tls.key"),
@@ -95,25 +383,452 @@ impl Default for ServerConfig {
 	}
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(rename_all = "camelCase")]
+pub struct HealthConfig {
+	watcher_grace_period_seconds: u64,
+}
+
+impl HealthConfig {
+	/// How long the namespace watcher may keep failing before the pod is reported as not ready
+	pub fn watcher_grace_period(&self) -> Duration {
+		Duration::from_secs(self.watcher_grace_period_seconds)
+	}
+}
+
+impl Default for HealthConfig {
+	fn default() -> Self {
+		HealthConfig {
+			watcher_grace_period_seconds: 30,
+		}
+	}
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct GroupConfig {
-	pub node_selector: Option<HashMap<String, String>>,
+	pub node_selector: Option<BTreeMap<String, String>>,
 	pub affinity: Option<Vec<String>>,
 	pub tolerations: Option<Vec<Toleration>>,
+	pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+	pub priority_class_name: Option<String>,
+	pub scheduler_name: Option<String>,
+	pub runtime_class_name: Option<String>,
+	pub pod_labels: Option<BTreeMap<String, String>>,
+	pub pod_annotations: Option<BTreeMap<String, String>>,
+	pub resources: Option<ResourcesConfig>,
+	pub injections: Option<Vec<InjectionConfig>>,
+	pub images: Option<ImagesConfig>,
+	/// Pods matching these are exempt in this group only, in addition to the global exemptions
+	pub exemptions: Option<ExemptionsConfig>,
+	/// Fields the group's namespaces may extend or override with annotations, such as `pod-director/tolerations`
+	pub allow_namespace_overrides: Option<Vec<String>>,
 	#[serde(default)]
 	pub on_conflict: Conflict,
+	/// Overrides the global mode for this group
+	pub mode: Option<Mode>,
+}
+
+impl GroupConfig {
+	/// Applies the overrides in a namespace's annotations, failing if any is invalid or not allowed by the group
+	///
+	/// Maps are extended, with the namespace's values taking precedence, tolerations are appended, topology spread
+	/// constraints replace the group's for the same topology key and anything else is replaced.
+	pub fn with_namespace_overrides(&self, namespace_annotations: &BTreeMap<String, String>) -> Result<Cow<'_, GroupConfig>, String> {
+		let mut overrides: Option<GroupConfig> = None;
+
+		for (key, value) in namespace_annotations {
+			let Some((field, parse)) = key.strip_prefix(NAMESPACE_OVERRIDE_PREFIX)
+				.and_then(|f| NAMESPACE_OVERRIDABLE_FIELDS.iter().find(|(name, _)| *name == f)) else {
+				continue;
+			};
+
+			if !self.allow_namespace_overrides.iter().flatten().any(|allowed| allowed == field) {
+				return Err(format!("The namespace's annotation {key} overrides {field}, which its group doesn't allow"));
+			}
+
+			parse(overrides.get_or_insert_with(GroupConfig::default), value)
+				.map_err(|e| format!("The namespace's annotation {key} is invalid: {e}"))?;
+		}
+
+		let Some(overrides) = overrides else {
+			return Ok(Cow::Borrowed(self));
+		};
+
+		// The group itself is validated along with the rest of the configuration, only the namespace's values are checked here
+		let errors = overrides.validate("namespaceOverrides");
+		if !errors.is_empty() {
+			let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+			return Err(format!("The namespace's overrides are invalid: {}", errors.join("; ")));
+		}
+
+		let mut config = self.clone();
+		extend_map(&mut config.node_selector, overrides.node_selector);
+		extend_map(&mut config.pod_labels, overrides.pod_labels);
+		extend_map(&mut config.pod_annotations, overrides.pod_annotations);
+
+		if let Some(tolerations) = overrides.tolerations {
+			config.tolerations.get_or_insert_with(Vec::new).extend(tolerations);
+		}
+
+		if let Some(constraints) = overrides.topology_spread_constraints {
+			let existing = config.topology_spread_constraints.get_or_insert_with(Vec::new);
+			existing.retain(|e| !constraints.iter().any(|c| c.topology_key == e.topology_key));
+			existing.extend(constraints);
+		}
+
+		config.priority_class_name = overrides.priority_class_name.or(config.priority_class_name);
+		config.scheduler_name = overrides.scheduler_name.or(config.scheduler_name);
+		config.runtime_class_name = overrides.runtime_class_name.or(config.runtime_class_name);
+
+		Ok(Cow::Owned(config))
+	}
+
+	fn validate(&self, field: &str) -> Vec<ConfigError> {
+		let mut errors = Vec::new();
+
+		for (i, allowed) in self.allow_namespace_overrides.iter().flatten().enumerate() {
+			if !is_namespace_overridable(allowed) {
+				let fields: Vec<&str> = NAMESPACE_OVERRIDABLE_FIELDS.iter().map(|(name, _)| *name).collect();
+				errors.push(invalid(
+					format!("{field}.allowNamespaceOverrides[{i}]"),
+					format!("\"{allowed}\" cannot be overridden by namespaces, must be one of {}", fields.join(", ")),
+				));
+			}
+		}
+
+		if let Some(node_selector) = &self.node_selector {
+			errors.extend(validate_labels(&format!("{field}.nodeSelector"), node_selector));
+		}
+
+		if let Some(pod_labels) = &self.pod_labels {
+			errors.extend(validate_labels(&format!("{field}.podLabels"), pod_labels));
+		}
+
+		if let Some(pod_annotations) = &self.pod_annotations {
+			for k in pod_annotations.keys() {
+				if !label::is_valid_key(k) {
+					errors.push(invalid(format!("{field}.podAnnotations"), format!("\"{k}\" is not a valid annotation key")));
+				}
+			}
+		}
+
+		if let Some(tolerations) = &self.tolerations {
+			for (i, toleration) in tolerations.iter().enumerate() {
+				let operator = toleration.operator.as_deref().unwrap_or("Equal");
+				if operator != "Equal" && operator != "Exists" {
+					errors.push(invalid(
+						format!("{field}.tolerations[{i}].operator"),
+						format!("\"{operator}\" is not a valid operator, must be either \"Equal\" or \"Exists\""),
+					));
+				}
+			}
+		}
+
+		if let Some(constraints) = &self.topology_spread_constraints {
+			let mut topology_keys = Vec::new();
+
+			for (i, constraint) in constraints.iter().enumerate() {
+				let constraint_field = format!("{field}.topologySpreadConstraints[{i}]");
+				let topology_key = &constraint.topology_key;
+
+				if !label::is_valid_key(topology_key) {
+					errors.push(invalid(format!("{constraint_field}.topologyKey"), format!("\"{topology_key}\" is not a valid label key")));
+				}
+				else if topology_keys.contains(&topology_key) {
+					errors.push(invalid(
+						format!("{constraint_field}.topologyKey"),
+						format!("\"{topology_key}\" is used by more than one constraint, only one is added per topology key"),
+					));
+				}
+				topology_keys.push(topology_key);
+
+				if constraint.max_skew < 1 {
+					errors.push(invalid(format!("{constraint_field}.maxSkew"), format!("{} must be greater than zero", constraint.max_skew)));
+				}
+
+				let when_unsatisfiable = &constraint.when_unsatisfiable;
+				if when_unsatisfiable != "DoNotSchedule" && when_unsatisfiable != "ScheduleAnyway" {
+					errors.push(invalid(
+						format!("{constraint_field}.whenUnsatisfiable"),
+						format!("\"{when_unsatisfiable}\" is not valid, must be either \"DoNotSchedule\" or \"ScheduleAnyway\""),
+					));
+				}
+			}
+		}
+
+		if let Some(resources) = &self.resources {
+			errors.extend(resources.validate(&format!("{field}.resources")));
+		}
+
+		if let Some(exemptions) = &self.exemptions {
+			errors.extend(exemptions.validate(&format!("{field}.exemptions")));
+		}
+
+		if let Some(images) = &self.images {
+			let patterns = [("allowed", &images.allowed), ("denied", &images.denied)];
+
+			for (name, patterns) in patterns {
+				for (i, pattern) in patterns.iter().flatten().enumerate() {
+					if pattern.is_empty() || pattern.contains(char::is_whitespace) {
+						errors.push(invalid(format!("{field}.images.{name}[{i}]"), format!("\"{pattern}\" is not a valid image pattern")));
+					}
+				}
+			}
+		}
+
+		errors
+	}
+}
+
+/// Resources for every container and init container of a group's pods, keyed by resource name such as `cpu`
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourcesConfig {
+	/// Requests set on containers that don't request the resource
+	#[serde(default, deserialize_with = "quantities")]
+	pub default_requests: Option<BTreeMap<String, Quantity>>,
+	/// Limits set on containers that don't limit the resource
+	#[serde(default, deserialize_with = "quantities")]
+	pub default_limits: Option<BTreeMap<String, Quantity>>,
+	/// Pods requesting more than this in total, after defaults are applied, are denied
+	#[serde(default, deserialize_with = "quantities")]
+	pub max_requests: Option<BTreeMap<String, Quantity>>,
+}
+
+/// Quantities such as `cpu: 1` are usually left unquoted, which YAML parses as numbers
+fn quantities<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BTreeMap<String, Quantity>>, D::Error> {
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum RawQuantity {
+		String(String),
+		Integer(i64),
+		Float(f64),
+	}
+
+	let raw: Option<BTreeMap<String, RawQuantity>> = Option::deserialize(deserializer)?;
+
+	Ok(raw.map(|quantities| quantities.into_iter()
+		.map(|(resource, q)| {
+			let q = match q {
+				RawQuantity::String(s) => s,
+				RawQuantity::Integer(i) => i.to_string(),
+				RawQuantity::Float(f) => f.to_string(),
+			};
+			(resource, Quantity(q))
+		})
+		.collect()))
+}
+
+impl ResourcesConfig {
+	fn validate(&self, field: &str) -> Vec<ConfigError> {
+		let mut errors = Vec::new();
+
+		let quantities = [
+			("defaultRequests", &self.default_requests),
+			("defaultLimits", &self.default_limits),
+			("maxRequests", &self.max_requests),
+		];
+
+		for (name, resources) in quantities {
+			for (resource, q) in resources.iter().flatten() {
+				if quantity::parse(&q.0).is_none() {
+					errors.push(invalid(format!("{field}.{name}.{resource}"), format!("\"{}\" is not a valid quantity", q.0)));
+				}
+			}
+		}
+
+		if let (Some(requests), Some(limits)) = (&self.default_requests, &self.default_limits) {
+			for (resource, limit) in limits {
+				let Some(request) = requests.get(resource) else { continue };
+
+				if let (Some(parsed_request), Some(parsed_limit)) = (quantity::parse(&request.0), quantity::parse(&limit.0)) {
+					if parsed_request > parsed_limit {
+						errors.push(invalid(
+							format!("{field}.defaultRequests.{resource}"),
+							format!("\"{}\" is greater than the default limit \"{}\"", request.0, limit.0),
+						));
+					}
+				}
+			}
+		}
+
+		errors
+	}
+}
+
+/// Entries added to the group's pods, unless already present, for settings that only make sense on the group's nodes
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InjectionConfig {
+	/// Names of the containers or init containers to inject into, every one of them when not set
+	pub containers: Option<Vec<String>>,
+	/// Added to each container unless it has a variable with the same name
+	pub env: Option<Vec<EnvVar>>,
+	/// Added to each container unless it has a mount at the same path
+	pub volume_mounts: Option<Vec<VolumeMount>>,
+	/// Added to the pod unless it has a volume with the same name, if any of its containers is injected into
+	pub volumes: Option<Vec<Volume>>,
+}
+
+/// Image patterns for every container and init container, where `*` matches any characters
+///
+/// Patterns are matched against both the image as written and its normalized form, so `docker.io/*` also matches
+/// `alpine`.
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImagesConfig {
+	/// Images must match at least one of these, any image is allowed when not set
+	pub allowed: Option<Vec<String>>,
+	/// Images must not match any of these, even if allowed
+	pub denied: Option<Vec<String>>,
+}
+
+impl ImagesConfig {
+	pub fn allows(&self, reference: &str) -> bool {
+		let allowed = self.allowed.as_ref()
+			.is_none_or(|patterns| patterns.iter().any(|p| image::matches(p, reference)));
+		let denied = self.denied.iter().flatten().any(|p| image::matches(p, reference));
+
+		allowed && !denied
+	}
+}
+
+/// A custom resource embedding a pod spec, mutated by the workload webhook like the built-in workloads
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomResourceConfig {
+	/// API group of the resource, such as `argoproj.io`
+	pub group: String,
+	pub kind: String,
+	/// JSON pointer to the embedded pod spec, such as `/spec/template/spec`
+	pub pod_spec_path: String,
+	/// JSON pointer to the embedded pod's metadata, without it labels and annotations are never set
+	pub pod_metadata_path: Option<String>,
+}
+
+impl CustomResourceConfig {
+	/// `None` if any of the paths is not a valid JSON pointer
+	pub fn pod_paths(&self) -> Option<PodPaths> {
+		let metadata = match &self.pod_metadata_path {
+			Some(path) => Some(Pointer::from_escaped(path)?),
+			None => None,
+		};
+
+		Some(PodPaths { metadata, spec: Pointer::from_escaped(&self.pod_spec_path)? })
+	}
+
+	fn validate(&self, field: &str) -> Vec<ConfigError> {
+		let mut errors = Vec::new();
+
+		if self.kind.is_empty() {
+			errors.push(invalid(format!("{field}.kind"), "the kind must not be empty".into()));
+		}
+
+		let paths = [("podSpecPath", Some(&self.pod_spec_path)), ("podMetadataPath", self.pod_metadata_path.as_ref())];
+		for (name, path) in paths {
+			let Some(path) = path else { continue };
+
+			if path.is_empty() || Pointer::from_escaped(path).is_none() {
+				errors.push(invalid(format!("{field}.{name}"), format!("\"{path}\" is not a valid JSON pointer")));
+			}
+		}
+
+		errors
+	}
+}
+
+/// Pods allowed unchanged, such as system components, if they match any of the criteria
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExemptionsConfig {
+	/// Pods with any of these labels
+	pub pod_labels: Option<BTreeMap<String, String>>,
+	/// Pods running as any of these service accounts
+	pub service_accounts: Option<Vec<String>>,
+	/// Pods with an owner reference of any of these kinds, such as `DaemonSet`, or `Node` for static pods
+	pub owner_kinds: Option<Vec<String>>,
+	/// Pods created by any of these users
+	pub users: Option<Vec<String>>,
+	/// Pods created by a member of any of these user groups
+	pub user_groups: Option<Vec<String>>,
+}
+
+impl ExemptionsConfig {
+	/// Describes why the pod is exempt, if it matches any of the criteria
+	pub fn exemption(&self, pod: &Pod, user_info: &UserInfo) -> Option<String> {
+		let contains = |list: &Option<Vec<String>>, value: &str| list.iter().flatten().any(|v| v == value);
+
+		if let (Some(exempt_labels), Some(pod_labels)) = (&self.pod_labels, &pod.metadata.labels) {
+			if let Some((k, v)) = exempt_labels.iter().find(|(k, v)| pod_labels.get(*k) == Some(v)) {
+				return Some(format!("the pod has the label {k}={v}"));
+			}
+		}
+
+		let service_account = pod.spec.as_ref()
+			.and_then(|s| s.service_account_name.as_deref())
+			.unwrap_or("default");
+		if contains(&self.service_accounts, service_account) {
+			return Some(format!("the pod runs as the service account {service_account}"));
+		}
+
+		if let Some(owner) = pod.metadata.owner_references.iter().flatten().find(|o| contains(&self.owner_kinds, &o.kind)) {
+			return Some(format!("the pod is owned by the {} {}", owner.kind, owner.name));
+		}
+
+		if let Some(username) = user_info.username.as_deref().filter(|u| contains(&self.users, u)) {
+			return Some(format!("the pod was created by the user {username}"));
+		}
+
+		if let Some(group) = user_info.groups.iter().flatten().find(|g| contains(&self.user_groups, g)) {
+			return Some(format!("the pod was created by a member of the group {group}"));
+		}
+
+		None
+	}
+
+	fn validate(&self, field: &str) -> Vec<ConfigError> {
+		match &self.pod_labels {
+			Some(pod_labels) => validate_labels(&format!("{field}.podLabels"), pod_labels),
+			None => Vec::new(),
+		}
+	}
+}
+
+fn extend_map(map: &mut Option<BTreeMap<String, String>>, overrides: Option<BTreeMap<String, String>>) {
+	if let Some(overrides) = overrides {
+		map.get_or_insert_with(BTreeMap::new).extend(overrides);
+	}
+}
+
+fn validate_labels(field: &str, labels: &BTreeMap<String, String>) -> Vec<ConfigError> {
+	let mut errors = Vec::new();
+
+	for (k, v) in labels {
+		if !label::is_valid_key(k) {
+			errors.push(invalid(field, format!("\"{k}\" is not a valid label key")));
+		}
+		if !label::is_valid_value(v) {
+			errors.push(invalid(format!("{field}.{k}"), format!("\"{v}\" is not a valid label value")));
+		}
+	}
+
+	errors
 }
 
 #[cfg(test)]
 mod tests {
-	use std::collections::HashMap;
+	use std::borrow::Cow;
+	use std::collections::BTreeMap;
 
-	use figment::Jail;
+	use figment::{Figment, Jail};
+	use figment::providers::{Format, Yaml};
 	use indoc::indoc;
-	use k8s_openapi::api::core::v1::Toleration;
+	use k8s_openapi::api::core::v1::{Toleration, TopologySpreadConstraint};
+	use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 
-	use super::{Config, Conflict, DEFAULT_CONFIG_FILE, ENV_CONFIG_FILE, GroupConfig};
+	use super::{Config, Conflict, CustomResourceConfig, default_yaml, DEFAULT_CONFIG_FILE, ENV_CONFIG_FILE, ExemptionsConfig, GroupConfig, ImagesConfig, Mode, ResourcesConfig};
 
 	#[test]
 	fn given_valid_config_file_at_default_path_then_should_be_loaded() {
@@ -146,9 +861,9 @@ mod tests {
 
 			let config = Config::load()?;
 
-			let mut groups = HashMap::new();
+			let mut groups = BTreeMap::new();
 			groups.insert("foo".into(), GroupConfig {
-				node_selector: Some(HashMap::from([
+				node_selector: Some(BTreeMap::from([
 					("a".into(), "1".into()),
 					("b".into(), "2".into()),
 					("c".into(), "3".into()),
@@ -156,6 +871,7 @@ mod tests {
 				affinity: None,
 				tolerations: None,
 				on_conflict: Default::default(),
+				..Default::default()
 			});
 			groups.insert("bar".into(), GroupConfig {
 				node_selector: None,
@@ -169,15 +885,17 @@ mod tests {
 				}
 				]),
 				on_conflict: Default::default(),
+				..Default::default()
 			});
 			groups.insert("bazz".into(), GroupConfig {
 				node_selector: None,
 				affinity: Some(vec![]),
 				tolerations: None,
 				on_conflict: Default::default(),
+				..Default::default()
 			});
 			groups.insert("all".into(), GroupConfig {
-				node_selector: Some(HashMap::from([
+				node_selector: Some(BTreeMap::from([
 					("a".into(), "1".into()),
 					("b".into(), "2".into()),
 					("c".into(), "3".into()),
@@ -192,9 +910,10 @@ mod tests {
 				}
 				]),
 				on_conflict: Conflict::Override,
+				..Default::default()
 			});
 
-			assert_eq!(config, Config { groups, group_label: "pod-director/group".into(), server: Default::default() });
+			assert_eq!(config, Config { groups, group_label: "pod-director/group".into(), ..Default::default() });
 
 			Ok(())
 		});
@@ -219,15 +938,32 @@ mod tests {
 
 			let config = Config::load()?;
 
-			let mut groups = HashMap::new();
+			let mut groups = BTreeMap::new();
 			groups.insert("bar".into(), GroupConfig {
-				node_selector: Some(HashMap::from([("a".into(), "1".into())])),
+				node_selector: Some(BTreeMap::from([("a".into(), "1".into())])),
 				affinity: None,
 				tolerations: None,
 				on_conflict: Default::default(),
+				..Default::default()
 			});
 
-			assert_eq!(config, Config { groups, group_label: "pod-director/group".into(), server: Default::default() });
+			assert_eq!(config, Config { groups, group_label: "pod-director/group".into(), ..Default::default() });
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_value_provided_by_env_then_loading_file_alone_should_ignore_it() {
+		Jail::expect_with(|jail| {
+			jail.create_file("config.yaml", indoc! { r#"
+				groupLabel: example.com/group
+			"# })?;
+			jail.set_env("PD_GROUPLABEL", "not-valid-@-label");
+
+			let config = Config::load_file("config.yaml")?;
+
+			assert_eq!(config.group_label, "example.com/group");
 
 			Ok(())
 		});
@@ -245,20 +981,342 @@ mod tests {
 
 			let config = Config::load()?;
 
-			let mut groups = HashMap::new();
+			let mut groups = BTreeMap::new();
 			groups.insert("foo".into(), GroupConfig {
 				node_selector: None,
 				affinity: Some(vec!["a".into(), "b".into()]),
 				tolerations: None,
 				on_conflict: Default::default(),
+				..Default::default()
 			});
 
-			assert_eq!(config, Config { groups, group_label: "pod-director/group".into(), server: Default::default() });
+			assert_eq!(config, Config { groups, group_label: "pod-director/group".into(), ..Default::default() });
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_annotate_pods_disabled_in_file_then_should_load_value() {
+		Jail::expect_with(|jail| {
+			jail.create_file(DEFAULT_CONFIG_FILE, indoc! { r#"
+				annotatePods: false
+			"# })?;
+
+			let config = Config::load()?;
+
+			assert!(!config.annotate_pods);
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_global_and_group_modes_then_group_mode_should_take_precedence() {
+		Jail::expect_with(|jail| {
+			jail.create_file(DEFAULT_CONFIG_FILE, indoc! { r#"
+				mode: dryRun
+				groups:
+				  foo:
+				    mode: enforce
+				  bar: {}
+			"# })?;
+
+			let config = Config::load()?;
+
+			assert_eq!(config.mode, Mode::DryRun);
+			assert_eq!(config.group_mode(&config.groups["foo"]), Mode::Enforce);
+			assert_eq!(config.group_mode(&config.groups["bar"]), Mode::DryRun);
 
 			Ok(())
 		});
 	}
 
+	#[test]
+	fn given_default_yaml_then_should_contain_every_option_with_default_values() {
+		let config: Config = Figment::from(Yaml::string(&default_yaml())).extract().unwrap();
+
+		assert_eq!(config, Config::default());
+	}
+
+	#[test]
+	fn given_default_config_then_should_be_valid() {
+		assert!(Config::default().validate().is_empty());
+	}
+
+	#[test]
+	fn given_invalid_values_then_validation_should_report_all_of_them() {
+		let mut config = Config { group_label: "not-valid-@-label".into(), ..Default::default() };
+		config.groups.insert("Invalid@Group".into(), GroupConfig {
+			node_selector: Some(BTreeMap::from([("kubernetes.io/os".into(), "not valid".into())])),
+			affinity: None,
+			tolerations: Some(vec![Toleration {
+				key: Some("foo".into()),
+				operator: Some("Equals".into()),
+				..Default::default()
+			}]),
+			on_conflict: Default::default(),
+			..Default::default()
+		});
+
+		let errors: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+
+		assert_eq!(errors, vec![
+			r#"invalid value for "groupLabel": "not-valid-@-label" is not a valid label key"#,
+			r#"invalid value for "groups.Invalid@Group": "Invalid@Group" is not a valid label value and thus cannot be a group name"#,
+			r#"invalid value for "groups.Invalid@Group.nodeSelector.kubernetes.io/os": "not valid" is not a valid label value"#,
+			r#"invalid value for "groups.Invalid@Group.tolerations[0].operator": "Equals" is not a valid operator, must be either "Equal" or "Exists""#,
+		]);
+	}
+
+	#[test]
+	fn given_invalid_topology_spread_constraints_then_validation_should_report_them() {
+		let constraint = |topology_key: &str, max_skew, when_unsatisfiable: &str| TopologySpreadConstraint {
+			topology_key: topology_key.into(),
+			max_skew,
+			when_unsatisfiable: when_unsatisfiable.into(),
+			..Default::default()
+		};
+
+		let mut config = Config::default();
+		config.groups.insert("foo".into(), GroupConfig {
+			topology_spread_constraints: Some(vec![
+				constraint("topology.kubernetes.io/zone", 1, "DoNotSchedule"),
+				constraint("topology.kubernetes.io/zone", 0, "ScheduleAnyway"),
+				constraint("not a key", 1, "Never"),
+			]),
+			..Default::default()
+		});
+
+		let errors: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+
+		assert_eq!(errors, vec![
+			r#"invalid value for "groups.foo.topologySpreadConstraints[1].topologyKey": "topology.kubernetes.io/zone" is used by more than one constraint, only one is added per topology key"#,
+			r#"invalid value for "groups.foo.topologySpreadConstraints[1].maxSkew": 0 must be greater than zero"#,
+			r#"invalid value for "groups.foo.topologySpreadConstraints[2].topologyKey": "not a key" is not a valid label key"#,
+			r#"invalid value for "groups.foo.topologySpreadConstraints[2].whenUnsatisfiable": "Never" is not valid, must be either "DoNotSchedule" or "ScheduleAnyway""#,
+		]);
+	}
+
+	#[test]
+	fn given_invalid_pod_labels_and_annotations_then_validation_should_report_them() {
+		let mut config = Config::default();
+		config.groups.insert("foo".into(), GroupConfig {
+			pod_labels: Some(BTreeMap::from([
+				("cost-center".into(), "not valid".into()),
+				("not@valid".into(), "value".into()),
+			])),
+			pod_annotations: Some(BTreeMap::from([
+				("example.com/any value is fine".into(), "but not keys".into()),
+			])),
+			..Default::default()
+		});
+
+		let errors: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+
+		assert_eq!(errors, vec![
+			r#"invalid value for "groups.foo.podLabels.cost-center": "not valid" is not a valid label value"#,
+			r#"invalid value for "groups.foo.podLabels": "not@valid" is not a valid label key"#,
+			r#"invalid value for "groups.foo.podAnnotations": "example.com/any value is fine" is not a valid annotation key"#,
+		]);
+	}
+
+	#[test]
+	fn given_namespace_overrides_then_fields_should_be_extended_or_replaced() {
+		let zone_constraint = |max_skew| TopologySpreadConstraint {
+			topology_key: "topology.kubernetes.io/zone".into(),
+			max_skew,
+			when_unsatisfiable: "ScheduleAnyway".into(),
+			..Default::default()
+		};
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([("a".into(), "1".into()), ("b".into(), "2".into())])),
+			topology_spread_constraints: Some(vec![zone_constraint(1)]),
+			priority_class_name: Some("low".into()),
+			allow_namespace_overrides: Some(vec!["nodeSelector".into(), "topologySpreadConstraints".into(), "priorityClassName".into()]),
+			..Default::default()
+		};
+		let annotations = BTreeMap::from([
+			("pod-director/nodeSelector".into(), "{b: \"3\", c: \"4\"}".into()),
+			("pod-director/topologySpreadConstraints".into(), indoc! { r#"
+				- topologyKey: topology.kubernetes.io/zone
+				  maxSkew: 2
+				  whenUnsatisfiable: ScheduleAnyway
+			"# }.into()),
+			("pod-director/priorityClassName".into(), "high".into()),
+		]);
+
+		let overridden = group_config.with_namespace_overrides(&annotations).unwrap();
+
+		assert_eq!(overridden.node_selector, Some(BTreeMap::from([
+			("a".into(), "1".into()),
+			("b".into(), "3".into()),
+			("c".into(), "4".into()),
+		])));
+		assert_eq!(overridden.topology_spread_constraints, Some(vec![zone_constraint(2)]));
+		assert_eq!(overridden.priority_class_name, Some("high".into()));
+	}
+
+	#[test]
+	fn given_misconfigured_group_then_namespace_overrides_should_only_check_the_namespace_values() {
+		let group_config = GroupConfig {
+			tolerations: Some(vec![Toleration { operator: Some("Maybe".into()), ..Default::default() }]),
+			allow_namespace_overrides: Some(vec!["tolerations".into()]),
+			..Default::default()
+		};
+		let annotations = BTreeMap::from([("pod-director/tolerations".into(), "[{key: gpu, operator: Exists}]".into())]);
+
+		let overridden = group_config.with_namespace_overrides(&annotations).unwrap();
+
+		assert_eq!(overridden.tolerations.as_ref().map(Vec::len), Some(2));
+	}
+
+	#[test]
+	fn given_no_namespace_overrides_then_group_config_should_be_borrowed() {
+		let group_config = GroupConfig::default();
+		let annotations = BTreeMap::from([("example.com/owner".into(), "team-a".into())]);
+
+		assert!(matches!(group_config.with_namespace_overrides(&annotations), Ok(Cow::Borrowed(_))));
+	}
+
+	#[test]
+	fn given_unsupported_namespace_overrides_then_validation_should_report_them() {
+		let mut config = Config::default();
+		config.groups.insert("foo".into(), GroupConfig {
+			allow_namespace_overrides: Some(vec!["tolerations".into(), "images".into()]),
+			..Default::default()
+		});
+
+		let errors: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+
+		assert_eq!(errors, vec![
+			r#"invalid value for "groups.foo.allowNamespaceOverrides[1]": "images" cannot be overridden by namespaces, must be one of nodeSelector, tolerations, topologySpreadConstraints, priorityClassName, schedulerName, runtimeClassName, podLabels, podAnnotations"#,
+		]);
+	}
+
+	#[test]
+	fn given_invalid_exemption_labels_then_validation_should_report_them() {
+		let mut config = Config {
+			exemptions: ExemptionsConfig {
+				pod_labels: Some(BTreeMap::from([("not@valid".into(), "true".into())])),
+				..Default::default()
+			},
+			..Default::default()
+		};
+		config.groups.insert("foo".into(), GroupConfig {
+			exemptions: Some(ExemptionsConfig {
+				pod_labels: Some(BTreeMap::from([("exempt".into(), "not valid".into())])),
+				..Default::default()
+			}),
+			..Default::default()
+		});
+
+		let errors: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+
+		assert_eq!(errors, vec![
+			r#"invalid value for "exemptions.podLabels": "not@valid" is not a valid label key"#,
+			r#"invalid value for "groups.foo.exemptions.podLabels.exempt": "not valid" is not a valid label value"#,
+		]);
+	}
+
+	#[test]
+	fn given_invalid_custom_resources_then_validation_should_report_them() {
+		let resource = |kind: &str, pod_spec_path: &str| CustomResourceConfig {
+			group: "example.com".into(),
+			kind: kind.into(),
+			pod_spec_path: pod_spec_path.into(),
+			pod_metadata_path: Some("/spec/template/metadata".into()),
+		};
+		let config = Config {
+			custom_resources: vec![
+				resource("Runner", "/spec/template/spec"),
+				resource("Runner", "spec.template.spec"),
+				resource("", "/spec/pod~2spec"),
+			],
+			..Default::default()
+		};
+
+		let errors: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+
+		assert_eq!(errors, vec![
+			r#"invalid value for "customResources[1].podSpecPath": "spec.template.spec" is not a valid JSON pointer"#,
+			r#"invalid value for "customResources[1].kind": "Runner" in group "example.com" is configured more than once"#,
+			r#"invalid value for "customResources[2].kind": the kind must not be empty"#,
+			r#"invalid value for "customResources[2].podSpecPath": "/spec/pod~2spec" is not a valid JSON pointer"#,
+		]);
+	}
+
+	#[test]
+	fn given_resources_with_unquoted_quantities_then_should_be_loaded() {
+		Jail::expect_with(|jail| {
+			jail.create_file(DEFAULT_CONFIG_FILE, indoc! { r#"
+				groups:
+				  foo:
+				    resources:
+				      defaultRequests:
+				        cpu: 100m
+				        memory: 128Mi
+				      maxRequests:
+				        cpu: 4
+			"# })?;
+
+			let config = Config::load()?;
+
+			assert_eq!(config.groups["foo"].resources, Some(ResourcesConfig {
+				default_requests: Some(BTreeMap::from([
+					("cpu".into(), Quantity("100m".into())),
+					("memory".into(), Quantity("128Mi".into())),
+				])),
+				default_limits: None,
+				max_requests: Some(BTreeMap::from([("cpu".into(), Quantity("4".into()))])),
+			}));
+
+			Ok(())
+		});
+	}
+
+	#[test]
+	fn given_invalid_resources_then_validation_should_report_them() {
+		let mut config = Config::default();
+		config.groups.insert("foo".into(), GroupConfig {
+			resources: Some(ResourcesConfig {
+				default_requests: Some(BTreeMap::from([
+					("cpu".into(), Quantity("2".into())),
+					("memory".into(), Quantity("lots".into())),
+				])),
+				default_limits: Some(BTreeMap::from([("cpu".into(), Quantity("1500m".into()))])),
+				max_requests: None,
+			}),
+			..Default::default()
+		});
+
+		let errors: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+
+		assert_eq!(errors, vec![
+			r#"invalid value for "groups.foo.resources.defaultRequests.memory": "lots" is not a valid quantity"#,
+			r#"invalid value for "groups.foo.resources.defaultRequests.cpu": "2" is greater than the default limit "1500m""#,
+		]);
+	}
+
+	#[test]
+	fn given_invalid_image_patterns_then_validation_should_report_them() {
+		let mut config = Config::default();
+		config.groups.insert("foo".into(), GroupConfig {
+			images: Some(ImagesConfig {
+				allowed: Some(vec!["registry.example.com/*".into(), "".into()]),
+				denied: Some(vec!["alpine latest".into()]),
+			}),
+			..Default::default()
+		});
+
+		let errors: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+
+		assert_eq!(errors, vec![
+			r#"invalid value for "groups.foo.images.allowed[1]": "" is not a valid image pattern"#,
+			r#"invalid value for "groups.foo.images.denied[0]": "alpine latest" is not a valid image pattern"#,
+		]);
+	}
+
 	#[test]
 	fn given_value_provided_by_env_and_by_file_then_should_load_value_from_env() {
 		Jail::expect_with(|jail| {
@@ -266,15 +1324,16 @@ mod tests {
 
 			let config = Config::load()?;
 
-			let mut groups = HashMap::new();
+			let mut groups = BTreeMap::new();
 			groups.insert("foo".into(), GroupConfig {
 				node_selector: None,
 				affinity: Some(vec!["a".into(), "b".into()]),
 				tolerations: None,
 				on_conflict: Default::default(),
+				..Default::default()
 			});
 
-			assert_eq!(config, Config { groups, group_label: "pod-director/group".into(), server: Default::default() });
+			assert_eq!(config, Config { groups, group_label: "pod-director/group".into(), ..Default::default() });
 
 			Ok(())
 		});
@@ -2,4 +2,4 @@ mod config;
 mod response;
 
 pub use config::ConfigError;
-pub use response::ResponseError;
+pub use response::{missing_group_config, ResponseError};
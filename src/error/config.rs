@@ -4,5 +4,8 @@ use std::path::PathBuf;
 #[derive(Error, Debug)]
 pub enum ConfigError {
 	#[error("failed loading certificates (cert: \"{cert_path}\"; and key: \"{key_path}\"): {source}")]
-	TlsConfig { source: anyhow::Error, cert_path: PathBuf, key_path: PathBuf }
+	TlsConfig { source: anyhow::Error, cert_path: PathBuf, key_path: PathBuf },
+
+	#[error("invalid value for \"{field}\": {reason}")]
+	Invalid { field: String, reason: String },
 }
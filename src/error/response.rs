@@ -1,8 +1,7 @@
 use axum::http::StatusCode;
 use axum::Json;
 use axum::response::{IntoResponse, Response};
-use k8s_openapi::api::core::v1::Pod;
-use kube::core::admission::{AdmissionRequest, AdmissionResponse, ConvertAdmissionReviewError};
+use kube::core::admission::{AdmissionResponse, ConvertAdmissionReviewError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -16,11 +15,18 @@ pub enum ResponseError {
 	#[error(transparent)]
 	KubernetesApi(#[from] kube::error::Error),
 
-	#[error("processed pod's namespace {0} doesn't contain a pod-director group label, the MutatingWebhookConfiguration is probably misconfigured", request.namespace.as_ref().unwrap_or(& "unknown".into()))]
-	NamespaceMissingLabel { request: AdmissionRequest<Pod> },
+	#[error("processed pod's namespace {namespace} doesn't contain a pod-director group label, the MutatingWebhookConfiguration is probably misconfigured")]
+	NamespaceMissingLabel { namespace: String, response: AdmissionResponse },
 
-	#[error("No pod-director group configured with the name {group}, the namespace {0} is misconfigured", request.namespace.as_ref().unwrap_or(& "unknown".into()))]
-	MissingGroupConfig { request: AdmissionRequest<Pod>, group: String },
+	#[error("{}", missing_group_config(.namespace, .group))]
+	MissingGroupConfig { namespace: String, group: String, response: AdmissionResponse },
+
+	#[error("pod-director doesn't mutate {kind} objects, the MutatingWebhookConfiguration is probably misconfigured")]
+	UnsupportedKind { kind: String, response: AdmissionResponse },
+
+	/// Embedded pod specs are optional in some custom resources, so such objects must still be allowed
+	#[error("processed object has no pod spec at {path}, it was not mutated")]
+	NoPodSpec { path: String, response: AdmissionResponse },
 }
 
 impl IntoResponse for ResponseError {
@@ -38,18 +44,31 @@ impl IntoResponse for ResponseError {
 				StatusCode::INTERNAL_SERVER_ERROR,
 				Json(AdmissionResponse::invalid(&self).into_review())
 			),
-			ResponseError::NamespaceMissingLabel { ref request } => {
-				let mut response = AdmissionResponse::from(request);
+			ResponseError::NamespaceMissingLabel { ref response, .. } => {
+				let mut response = response.clone();
+				response.warnings = Some(vec![self.to_string()]);
+				(
+					StatusCode::OK,
+					Json(response.into_review())
+				)
+			}
+			ResponseError::UnsupportedKind { ref response, .. } | ResponseError::NoPodSpec { ref response, .. } => {
+				let mut response = response.clone();
 				response.warnings = Some(vec![self.to_string()]);
 				(
 					StatusCode::OK,
 					Json(response.into_review())
 				)
 			}
-			ResponseError::MissingGroupConfig { ref request, .. } => (
+			ResponseError::MissingGroupConfig { ref response, .. } => (
 				StatusCode::OK,
-				Json(AdmissionResponse::from(request).deny(self.to_string()).into_review())
+				Json(response.clone().deny(self.to_string()).into_review())
 			),
 		}.into_response()
 	}
 }
+
+/// Also used to explain admission, which must give the same reason
+pub fn missing_group_config(namespace: &str, group: &str) -> String {
+	format!("No pod-director group configured with the name {group}, the namespace {namespace} is misconfigured")
+}
@@ -1,5 +1,11 @@
+mod explain;
 mod health;
+mod metrics;
 mod mutate;
+mod workload;
 
-pub use health::{health};
-pub use mutate::mutate;
+pub use explain::explain;
+pub use health::{livez, readyz};
+pub use metrics::metrics;
+pub use mutate::{decide, mutate, Mutation, Verdict};
+pub use workload::mutate_workload;
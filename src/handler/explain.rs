@@ -0,0 +1,364 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use json_patch::PatchOperation;
+use k8s_openapi::api::authentication::v1::UserInfo;
+use k8s_openapi::api::core::v1::Pod;
+use kube::core::admission::{AdmissionRequest, AdmissionReview};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::Mode;
+use crate::error::missing_group_config;
+use crate::handler::mutate::{decide, Mutation, Verdict};
+use crate::server::AppState;
+use crate::service::KubernetesService;
+use crate::utils::patch::{Decision, Outcome, PodPaths};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Explanation {
+	namespace: String,
+	group: Option<String>,
+	mode: Option<Mode>,
+	allowed: bool,
+	denial_reason: Option<String>,
+	decisions: Vec<Decision>,
+	patches: Vec<PatchOperation>,
+	summary: Vec<String>,
+}
+
+impl Explanation {
+	fn new(namespace: String) -> Self {
+		Self {
+			namespace,
+			group: None,
+			mode: None,
+			allowed: true,
+			denial_reason: None,
+			decisions: Vec::new(),
+			patches: Vec::new(),
+			summary: Vec::new(),
+		}
+	}
+}
+
+/// Explains how a Pod, or an AdmissionReview for a Pod, would be handled by `mutate`, without side effects
+pub async fn explain<S: AppState>(
+	State(app_state): State<S>,
+	Json(body): Json<Value>,
+) -> Result<Json<Explanation>, (StatusCode, String)> {
+	let (namespace, pod, user_info) = parse_input(body).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+	let document = serde_json::to_value(&pod).expect("Pod should always be serializable");
+
+	let config = app_state.config();
+	let group_label = &config.group_label;
+	let mut explanation = Explanation::new(namespace.clone());
+
+	let group = match app_state.kubernetes().namespace_group(&namespace).await {
+		Some(g) => g,
+		None => {
+			explanation.summary.push(format!(
+				"Namespace {namespace} has no {group_label} label, the pod would be allowed unchanged with a warning"
+			));
+			return Ok(Json(explanation));
+		}
+	};
+	explanation.summary.push(format!("Namespace {namespace} has the label {group_label}={group}, using group {group}"));
+	explanation.group = Some(group.clone());
+
+	let namespace_annotations = app_state.kubernetes().namespace(&namespace).await
+		.map(|n| n.annotations)
+		.unwrap_or_default();
+	let verdict = decide(config, &group, &namespace_annotations, &document, &PodPaths::root(), &user_info, &mut explanation.decisions);
+
+	let (mode, mutation) = match verdict {
+		Verdict::NoPodSpec => return Err((StatusCode::BAD_REQUEST, "Pod spec is missing".into())),
+		Verdict::Exempt(reason) => {
+			explanation.summary.push(format!("{reason}, the pod would be allowed unchanged"));
+			return Ok(Json(explanation));
+		}
+		Verdict::MissingGroupConfig => {
+			let reason = missing_group_config(&namespace, &group);
+			explanation.summary.push(format!("The pod would be denied: {reason}"));
+			explanation.allowed = false;
+			explanation.denial_reason = Some(reason);
+			return Ok(Json(explanation));
+		}
+		Verdict::Mutate { group_config, mode, overridden, mutation } => {
+			explanation.summary.push(format!(
+				"Group {group} runs in {} mode and handles conflicts with {:?}",
+				mode.as_str(),
+				group_config.on_conflict,
+			));
+			if overridden {
+				explanation.summary.push(format!("Namespace {namespace} overrides parts of group {group} with its annotations"));
+			}
+			(mode, mutation)
+		}
+	};
+	explanation.mode = Some(mode);
+	explanation.summary.extend(explanation.decisions.iter().map(describe));
+
+	match mutation {
+		Mutation::Patch(patches) => {
+			explanation.summary.push(format!("The pod would be allowed with {} patch operations", patches.len()));
+			explanation.patches = patches;
+		}
+		Mutation::Deny(reason) => {
+			explanation.summary.push(format!("The pod would be denied: {reason}"));
+			explanation.allowed = false;
+			explanation.denial_reason = Some(reason);
+		}
+	}
+
+	if mode == Mode::DryRun {
+		explanation.summary.push("Since the group is in dry run mode, the pod would be allowed unchanged and the result only reported as warnings".into());
+		explanation.allowed = true;
+	}
+
+	Ok(Json(explanation))
+}
+
+/// Only AdmissionReviews carry the requesting user, plain pods are explained as if requested by an unknown user
+fn parse_input(body: Value) -> Result<(String, Pod, UserInfo), String> {
+	if body.get("kind").and_then(Value::as_str) == Some("AdmissionReview") {
+		let review: AdmissionReview<Pod> = serde_json::from_value(body)
+			.map_err(|e| format!("Failed parsing AdmissionReview: {e}"))?;
+		let request: AdmissionRequest<Pod> = review.try_into()
+			.map_err(|e| format!("Failed converting request into AdmissionRequest for Pod: {e}"))?;
+
+		let namespace = request.namespace.ok_or("Request has no namespace defined")?;
+		let pod = request.object.ok_or("Request has no object")?;
+		Ok((namespace, pod, request.user_info))
+	}
+	else {
+		let pod: Pod = serde_json::from_value(body).map_err(|e| format!("Failed parsing Pod: {e}"))?;
+
+		let namespace = pod.metadata.namespace.clone().ok_or("Pod has no namespace defined")?;
+		Ok((namespace, pod, UserInfo::default()))
+	}
+}
+
+fn describe(decision: &Decision) -> String {
+	let subject = match &decision.key {
+		Some(key) => format!("{} {key}", decision.field),
+		None => decision.field.to_string(),
+	};
+	let config_value = &decision.config_value;
+	let pod_value = decision.pod_value.as_ref().unwrap_or(&Value::Null);
+
+	match decision.outcome {
+		Outcome::Added => format!("{subject}: the pod has no such value, adding {config_value}"),
+		Outcome::Unchanged => format!("{subject}: the pod already has {config_value}"),
+		Outcome::Ignored => format!("{subject}: the pod has {pod_value} instead of {config_value}, keeping it since conflicts are ignored"),
+		Outcome::Overridden => format!("{subject}: the pod has {pod_value} instead of {config_value}, replacing it since conflicts are overridden"),
+		Outcome::Rejected => format!("{subject}: the pod has {pod_value} instead of {config_value}, denying it since conflicts are rejected"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use axum::body::Body;
+	use axum::http::{Request, StatusCode};
+	use http_body_util::BodyExt;
+	use serde_json::{json, Value};
+	use tower::ServiceExt;
+
+	use crate::config::{Config, Conflict, ExemptionsConfig, GroupConfig, Mode};
+	use crate::server;
+	use crate::server::state::tests::TestAppState;
+	use crate::test_utils::PodCreateRequestBuilder;
+
+	fn pod(node_selector: Value) -> Body {
+		Body::from(json!({
+			"apiVersion": "v1",
+			"kind": "Pod",
+			"metadata": {"name": "test", "namespace": "foo"},
+			"spec": {
+				"containers": [{"name": "test", "image": "alpine"}],
+				"nodeSelector": node_selector,
+			},
+		}).to_string())
+	}
+
+	fn state(on_conflict: Conflict, mode: Option<Mode>) -> TestAppState {
+		let config = Config {
+			groups: BTreeMap::from([
+				("bar".into(), GroupConfig {
+					node_selector: Some(BTreeMap::from([
+						("label-0".into(), "value-0".into()),
+					])),
+					on_conflict,
+					mode,
+					..Default::default()
+				})
+			]),
+			annotate_pods: false,
+			..Default::default()
+		};
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+		state
+	}
+
+	async fn explain_request(state: TestAppState, body: Body) -> (StatusCode, Value) {
+		let request = Request::builder()
+			.uri("/explain")
+			.header("Content-Type", "application/json")
+			.method("POST")
+			.body(body)
+			.unwrap();
+
+		let response = server::build_ops_app(state)
+			.oneshot(request)
+			.await
+			.unwrap();
+
+		let status = response.status();
+		let body = response.into_body().collect().await.unwrap().to_bytes();
+		(status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+	}
+
+	#[tokio::test]
+	async fn when_explaining_pod_without_node_selector_should_list_added_values_and_patches() {
+		let (status, body) = explain_request(state(Conflict::Reject, None), pod(Value::Null)).await;
+
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["group"], json!("bar"));
+		assert_eq!(body["mode"], json!("enforce"));
+		assert_eq!(body["allowed"], json!(true));
+		assert_eq!(body["decisions"], json!([{
+			"field": "nodeSelector",
+			"key": "label-0",
+			"configValue": "value-0",
+			"podValue": null,
+			"outcome": "added",
+		}]));
+		assert_eq!(body["patches"], json!([
+			{"op": "add", "path": "/spec/nodeSelector", "value": {"label-0": "value-0"}},
+		]));
+		assert_eq!(body["summary"], json!([
+			"Namespace foo has the label pod-director/group=bar, using group bar",
+			"Group bar runs in enforce mode and handles conflicts with Reject",
+			r#"nodeSelector label-0: the pod has no such value, adding "value-0""#,
+			"The pod would be allowed with 1 patch operations",
+		]));
+	}
+
+	#[tokio::test]
+	async fn when_explaining_conflicting_pod_should_show_rejection() {
+		let (status, body) = explain_request(state(Conflict::Reject, None), pod(json!({"label-0": "other"}))).await;
+
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["allowed"], json!(false));
+		assert_eq!(body["decisions"][0]["outcome"], json!("rejected"));
+		assert_eq!(body["decisions"][0]["podValue"], json!("other"));
+		assert_eq!(
+			body["denialReason"],
+			json!("The pod's nodeSelector label-0=other conflicts with pod-director's configuration label-0=value-0")
+		);
+	}
+
+	#[tokio::test]
+	async fn when_explaining_admission_review_should_use_request_namespace() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_node_selector("label-0", "other")
+			.build();
+
+		let (status, body) = explain_request(state(Conflict::Override, None), body).await;
+
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["namespace"], json!("foo"));
+		assert_eq!(body["decisions"][0]["outcome"], json!("overridden"));
+		assert_eq!(body["patches"], json!([
+			{"op": "replace", "path": "/spec/nodeSelector/label-0", "value": "value-0"},
+		]));
+	}
+
+	#[tokio::test]
+	async fn when_explaining_pod_in_dry_run_group_should_be_allowed() {
+		let (status, body) = explain_request(state(Conflict::Reject, Some(Mode::DryRun)), pod(json!({"label-0": "other"}))).await;
+
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["mode"], json!("dryRun"));
+		assert_eq!(body["allowed"], json!(true));
+		assert_eq!(body["decisions"][0]["outcome"], json!("rejected"));
+	}
+
+	#[tokio::test]
+	async fn when_explaining_exempt_pod_should_explain_exemption() {
+		let config = Config {
+			exemptions: ExemptionsConfig {
+				users: Some(vec!["user".into()]),
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let (status, body) = explain_request(state, body).await;
+
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["allowed"], json!(true));
+		assert_eq!(body["patches"], json!([]));
+		assert_eq!(body["summary"][1], json!("Exempt globally since the pod was created by the user user, the pod would be allowed unchanged"));
+	}
+
+	#[tokio::test]
+	async fn when_explaining_pod_in_namespace_without_group_should_explain_missing_label() {
+		let state = TestAppState::new(Config::default());
+
+		let (status, body) = explain_request(state, pod(Value::Null)).await;
+
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["group"], Value::Null);
+		assert_eq!(body["allowed"], json!(true));
+		assert_eq!(body["summary"], json!([
+			"Namespace foo has no pod-director/group label, the pod would be allowed unchanged with a warning",
+		]));
+	}
+
+	#[tokio::test]
+	async fn when_explaining_pod_without_spec_should_fail() {
+		let body = Body::from(json!({"apiVersion": "v1", "kind": "Pod", "metadata": {"name": "test", "namespace": "foo"}}).to_string());
+
+		let (status, _) = explain_request(state(Conflict::Reject, None), body).await;
+
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn when_explaining_pod_without_namespace_should_fail() {
+		let body = Body::from(json!({"apiVersion": "v1", "kind": "Pod", "metadata": {}, "spec": {"containers": []}}).to_string());
+
+		let (status, _) = explain_request(TestAppState::new(Config::default()), body).await;
+
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+	}
+	#[tokio::test]
+	async fn when_explaining_on_webhook_listener_should_not_be_found() {
+		let request = Request::builder()
+			.uri("/explain")
+			.header("Content-Type", "application/json")
+			.method("POST")
+			.body(pod(Value::Null))
+			.unwrap();
+
+		let response = server::build_app(state(Conflict::Reject, None))
+			.oneshot(request)
+			.await
+			.unwrap();
+
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+}
@@ -1,53 +1,237 @@
+use std::time::Duration;
+
 use axum::extract::State;
 use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
 use crate::server::AppState;
 use crate::service::KubernetesService;
 
-pub async fn health<S: AppState>(State(app_state): State<S>) -> impl IntoResponse {
-	match app_state.kubernetes().healthy().await {
-		true => StatusCode::OK,
-		false => StatusCode::INTERNAL_SERVER_ERROR,
+static RUNTIME_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Debug)]
+pub struct HealthReport {
+	healthy: bool,
+	checks: Vec<HealthCheck>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HealthCheck {
+	name: &'static str,
+	healthy: bool,
+	message: String,
+}
+
+impl HealthReport {
+	fn from_checks(checks: Vec<HealthCheck>) -> Self {
+		Self {
+			healthy: checks.iter().all(|c| c.healthy),
+			checks,
+		}
+	}
+}
+
+impl HealthCheck {
+	fn pass<S: Into<String>>(name: &'static str, message: S) -> Self {
+		Self { name, healthy: true, message: message.into() }
+	}
+
+	fn fail<S: Into<String>>(name: &'static str, message: S) -> Self {
+		Self { name, healthy: false, message: message.into() }
 	}
 }
 
+impl IntoResponse for HealthReport {
+	fn into_response(self) -> Response {
+		let status = match self.healthy {
+			true => StatusCode::OK,
+			false => StatusCode::SERVICE_UNAVAILABLE,
+		};
+
+		(status, Json(self)).into_response()
+	}
+}
+
+/// Liveness only checks that the process is able to make progress, anything else could be fixed without a restart
+pub async fn livez() -> HealthReport {
+	let runtime = match tokio::time::timeout(RUNTIME_CHECK_TIMEOUT, tokio::spawn(async {})).await {
+		Ok(Ok(_)) => HealthCheck::pass("runtime", "runtime is scheduling tasks"),
+		_ => HealthCheck::fail("runtime", "runtime failed to schedule a task in time"),
+	};
+
+	HealthReport::from_checks(vec![runtime])
+}
+
+pub async fn readyz<S: AppState>(State(app_state): State<S>) -> HealthReport {
+	let config = app_state.config();
+	let watcher_status = app_state.kubernetes().watcher_status().await;
+	let grace_period = config.health.watcher_grace_period();
+
+	let namespace_cache = match watcher_status.synced {
+		true => HealthCheck::pass("namespaceCache", "namespace cache is synced"),
+		false => HealthCheck::fail("namespaceCache", "namespace cache has not been synced yet"),
+	};
+
+	let namespace_watcher = match watcher_status.failing_since.map(|since| since.elapsed()) {
+		None => HealthCheck::pass("namespaceWatcher", "namespace watcher is running"),
+		Some(failing_for) if failing_for < grace_period => HealthCheck::pass(
+			"namespaceWatcher",
+			format!("namespace watcher failing for {}s, within the grace period of {}s", failing_for.as_secs(), grace_period.as_secs()),
+		),
+		Some(failing_for) => HealthCheck::fail(
+			"namespaceWatcher",
+			format!("namespace watcher failing for {}s, exceeding the grace period of {}s", failing_for.as_secs(), grace_period.as_secs()),
+		),
+	};
+
+	let tls = match (config.server.insecure, app_state.tls_loaded()) {
+		(true, _) => HealthCheck::pass("tls", "TLS is disabled"),
+		(false, true) => HealthCheck::pass("tls", "TLS certificates are loaded"),
+		(false, false) => HealthCheck::fail("tls", "TLS certificates have not been loaded"),
+	};
+
+	let config_errors = config.validate();
+	let config = match config_errors.is_empty() {
+		true => HealthCheck::pass("config", "configuration is valid"),
+		false => HealthCheck::fail(
+			"config",
+			config_errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "),
+		),
+	};
+
+	HealthReport::from_checks(vec![namespace_cache, namespace_watcher, tls, config])
+}
+
 #[cfg(test)]
 mod tests {
+	use std::time::Duration;
+
 	use axum::body::Body;
 	use axum::http::{Request, StatusCode};
+	use http_body_util::BodyExt;
+	use serde_json::{json, Value};
 	use tower::ServiceExt;
 
 	use crate::config::Config;
 	use crate::server;
 	use crate::server::state::tests::TestAppState;
 
-	#[tokio::test]
-	async fn health_ok_test() {
-		let app = server::build_app(TestAppState::new(Config::default()));
-
-		let request = Request::builder().uri("/health").body(Body::empty()).unwrap();
+	async fn health_request(state: TestAppState, uri: &str) -> (StatusCode, Value) {
+		let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
 
-		let response = app
+		let response = server::build_ops_app(state)
 			.oneshot(request)
 			.await
 			.unwrap();
 
-		assert_eq!(response.status(), StatusCode::OK);
+		let status = response.status();
+		let body = response.into_body().collect().await.unwrap().to_bytes();
+		(status, serde_json::from_slice(&body).unwrap())
+	}
+
+	fn check<'a>(body: &'a Value, name: &str) -> &'a Value {
+		body["checks"].as_array().unwrap()
+			.iter()
+			.find(|c| c["name"] == name)
+			.unwrap()
 	}
 
 	#[tokio::test]
-	async fn health_err_test() {
+	async fn livez_ok_test() {
+		let (status, body) = health_request(TestAppState::new(Config::default()), "/livez").await;
+
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["healthy"], json!(true));
+	}
+
+	#[tokio::test]
+	async fn livez_ignores_watcher_errors_test() {
 		let mut app_state = TestAppState::new(Config::default());
-		app_state.kubernetes.set_error(true);
-		let app = server::build_app(app_state);
+		app_state.kubernetes.set_failing_for(Duration::from_secs(3600));
+
+		let (status, _) = health_request(app_state, "/livez").await;
 
-		let request = Request::builder().uri("/health").body(Body::empty()).unwrap();
+		assert_eq!(status, StatusCode::OK);
+	}
+
+	#[tokio::test]
+	async fn health_not_served_on_webhook_listener_test() {
+		let request = Request::builder().uri("/readyz").body(Body::empty()).unwrap();
 
-		let response = app
+		let response = server::build_app(TestAppState::new(Config::default()))
 			.oneshot(request)
 			.await
 			.unwrap();
 
-		assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn readyz_ok_test() {
+		let (status, body) = health_request(TestAppState::new(Config::default()), "/readyz").await;
+
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body["healthy"], json!(true));
+		assert_eq!(body["checks"].as_array().unwrap().len(), 4);
+	}
+
+	#[tokio::test]
+	async fn readyz_watcher_failing_within_grace_period_test() {
+		let mut app_state = TestAppState::new(Config::default());
+		app_state.kubernetes.set_failing_for(Duration::from_secs(5));
+
+		let (status, body) = health_request(app_state, "/readyz").await;
+
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(check(&body, "namespaceWatcher")["healthy"], json!(true));
+	}
+
+	#[tokio::test]
+	async fn readyz_watcher_failing_beyond_grace_period_test() {
+		let mut app_state = TestAppState::new(Config::default());
+		app_state.kubernetes.set_failing_for(Duration::from_secs(60));
+
+		let (status, body) = health_request(app_state, "/readyz").await;
+
+		assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+		assert_eq!(body["healthy"], json!(false));
+		assert_eq!(check(&body, "namespaceWatcher")["healthy"], json!(false));
+	}
+
+	#[tokio::test]
+	async fn readyz_not_synced_test() {
+		let mut app_state = TestAppState::new(Config::default());
+		app_state.kubernetes.set_synced(false);
+
+		let (status, body) = health_request(app_state, "/readyz").await;
+
+		assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+		assert_eq!(check(&body, "namespaceCache")["healthy"], json!(false));
+	}
+
+	#[tokio::test]
+	async fn readyz_tls_not_loaded_test() {
+		let mut app_state = TestAppState::new(Config::default());
+		app_state.tls_loaded = false;
+
+		let (status, body) = health_request(app_state, "/readyz").await;
+
+		assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+		assert_eq!(check(&body, "tls")["healthy"], json!(false));
+	}
+
+	#[tokio::test]
+	async fn readyz_invalid_config_test() {
+		let config = Config { group_label: "not-valid-@-label".into(), ..Default::default() };
+
+		let (status, body) = health_request(TestAppState::new(config), "/readyz").await;
+
+		assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+		assert_eq!(
+			check(&body, "config")["message"],
+			json!(r#"invalid value for "groupLabel": "not-valid-@-label" is not a valid label key"#)
+		);
 	}
 }
@@ -0,0 +1,43 @@
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+
+use crate::server::AppState;
+
+pub async fn metrics<S: AppState>(State(app_state): State<S>) -> impl IntoResponse {
+	(
+		[(header::CONTENT_TYPE, "application/openmetrics-text; version=1.0.0; charset=utf-8")],
+		app_state.metrics().encode(),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use axum::body::Body;
+	use axum::http::{Request, StatusCode};
+	use http_body_util::BodyExt;
+	use tower::ServiceExt;
+
+	use crate::config::{Config, Mode};
+	use crate::server;
+	use crate::server::state::tests::TestAppState;
+
+	#[tokio::test]
+	async fn metrics_should_render_recorded_admissions() {
+		let state = TestAppState::new(Config::default());
+		state.metrics.record_admission("foo", Mode::DryRun, true, 2);
+
+		let request = Request::builder().uri("/metrics").body(Body::empty()).unwrap();
+		let response = server::build_ops_app(state)
+			.oneshot(request)
+			.await
+			.unwrap();
+
+		assert_eq!(response.status(), StatusCode::OK);
+
+		let body = response.into_body().collect().await.unwrap().to_bytes();
+		let body = String::from_utf8(body.to_vec()).unwrap();
+		assert!(body.contains(r#"pod_director_admissions_total{group="foo",mode="dryRun",result="allowed"} 1"#));
+		assert!(body.contains(r#"pod_director_patches_total{group="foo",mode="dryRun"} 2"#));
+	}
+}
@@ -1,15 +1,32 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
 use axum::extract::State;
 use axum::Json;
 use axum::response::Result;
-use k8s_openapi::api::core::v1::Pod;
+use json_patch::PatchOperation;
+use k8s_openapi::api::authentication::v1::UserInfo;
+use k8s_openapi::api::core::v1::{Container, Pod, PodSpec};
 use kube::api::DynamicObject;
 use kube::core::admission::{AdmissionRequest, AdmissionResponse, AdmissionReview};
+use kube::Resource;
+use serde::Serialize;
+use serde_json::{json, Value};
 
+use crate::config::{Config, GroupConfig, ImagesConfig, Mode, ResourcesConfig};
 use crate::error::ResponseError;
 use crate::server::AppState;
 use crate::service::KubernetesService;
-use crate::utils::patch;
-use crate::utils::patch::PatchResult;
+use crate::utils::{patch, quantity};
+use crate::utils::patch::{Decision, PatchResult, PodPaths, Pointer};
+
+static GROUP_ANNOTATION: &str = "pod-director/group";
+static APPLIED_NODE_SELECTOR_ANNOTATION: &str = "pod-director/applied-node-selector";
+static OVERRIDDEN_KEYS_ANNOTATION: &str = "pod-director/overridden-keys";
+/// Set by the API server on every pod without a scheduler, so it's treated as not set at all
+static DEFAULT_SCHEDULER: &str = "default-scheduler";
+/// Prefixed by the API server with the webhook's name
+static EXEMPTION_AUDIT_ANNOTATION: &str = "exemption";
 
 pub async fn mutate<S: AppState>(
 	State(app_state): State<S>,
@@ -17,76 +34,448 @@ pub async fn mutate<S: AppState>(
 ) -> Result<Json<AdmissionReview<DynamicObject>>, ResponseError> {
 	let request: AdmissionRequest<Pod> = body.try_into()?;
 
+	let response = admit(&app_state, &request, &PodPaths::root()).await?;
+
+	Ok(Json(response.into_review()))
+}
+
+/// Applies the namespace's group to the pod at `paths` in the request's object, shared by every kind of admitted object
+pub async fn admit<S: AppState, K: Resource + Serialize>(
+	app_state: &S,
+	request: &AdmissionRequest<K>,
+	paths: &PodPaths,
+) -> Result<AdmissionResponse, ResponseError> {
 	let namespace = request.namespace.as_ref().ok_or(ResponseError::NoNamespace)?;
 
 	let group = match app_state.kubernetes().namespace_group(namespace).await {
 		Some(g) => g,
 		None => return Err(ResponseError::NamespaceMissingLabel {
-			request,
+			namespace: namespace.clone(),
+			response: AdmissionResponse::from(request),
 		}),
 	};
 
-	let group_config = match app_state.config().groups.get(&group) {
-		Some(group_config) => group_config,
-		None => return Err(ResponseError::MissingGroupConfig {
-			request,
+	let config = app_state.config();
+	let object = request.object.as_ref().expect("Request object is missing");
+	let document = serde_json::to_value(object).expect("Admitted objects should always be serializable");
+	let namespace_annotations = app_state.kubernetes().namespace(namespace).await
+		.map(|n| n.annotations)
+		.unwrap_or_default();
+
+	let verdict = decide(config, &group, &namespace_annotations, &document, paths, &request.user_info, &mut Vec::new());
+	let (mode, mutation) = match verdict {
+		Verdict::NoPodSpec => return Err(ResponseError::NoPodSpec {
+			path: paths.spec.to_string(),
+			response: AdmissionResponse::from(request),
+		}),
+		Verdict::Exempt(reason) => return Ok(exempt(request, reason)),
+		Verdict::MissingGroupConfig => return Err(ResponseError::MissingGroupConfig {
+			namespace: namespace.clone(),
 			group,
+			response: AdmissionResponse::from(request),
 		}),
+		Verdict::Mutate { mode, mutation, .. } => (mode, mutation),
+	};
+
+	let (response, allowed, patch_count) = match (mode, mutation) {
+		(Mode::Enforce, Mutation::Patch(patches)) => {
+			let patch_count = patches.len();
+			let response = AdmissionResponse::from(request)
+				.with_patch(json_patch::Patch(patches))
+				.unwrap();
+			(response, true, patch_count)
+		}
+		(Mode::Enforce, Mutation::Deny(reason)) => {
+			(AdmissionResponse::from(request).deny(reason), false, 0)
+		}
+		(Mode::DryRun, Mutation::Patch(patches)) => {
+			let warnings: Vec<String> = patches.iter()
+				.map(|p| format!("pod-director dry run for group {group}: would apply patch {}", serde_json::to_string(p).unwrap()))
+				.collect();
+			warnings.iter().for_each(|w| println!("{namespace}/{}: {w}", request.name));
+
+			let mut response = AdmissionResponse::from(request);
+			if !warnings.is_empty() {
+				response.warnings = Some(warnings);
+			}
+			(response, true, patches.len())
+		}
+		(Mode::DryRun, Mutation::Deny(reason)) => {
+			let warning = format!("pod-director dry run for group {group}: would deny pod: {reason}");
+			println!("{namespace}/{}: {warning}", request.name);
+
+			let mut response = AdmissionResponse::from(request);
+			response.warnings = Some(vec![warning]);
+			(response, false, 0)
+		}
+	};
+
+	// Requests such as "kubectl apply --dry-run=server" are never persisted, so they must not be counted
+	if !request.dry_run {
+		app_state.metrics().record_admission(&group, mode, allowed, patch_count);
+	}
+
+	Ok(response)
+}
+
+/// How a pod would be handled in its namespace's group
+pub enum Verdict<'a> {
+	/// There's no valid pod spec at the expected path
+	NoPodSpec,
+	/// Allowed unchanged, for the given reason
+	Exempt(String),
+	/// The namespace's group has no configuration
+	MissingGroupConfig,
+	Mutate {
+		group_config: &'a GroupConfig,
+		mode: Mode,
+		/// Whether the namespace's annotations override parts of the group's configuration
+		overridden: bool,
+		mutation: Mutation,
+	},
+}
+
+/// Decides how the pod at `paths` in `document` is handled in the given group, shared by admission and any tooling that
+/// must match it
+///
+/// Exemptions are checked before the group's configuration, so exempt pods are still allowed in misconfigured namespaces.
+pub fn decide<'a>(
+	config: &'a Config,
+	group: &str,
+	namespace_annotations: &BTreeMap<String, String>,
+	document: &Value,
+	paths: &PodPaths,
+	user_info: &UserInfo,
+	decisions: &mut Vec<Decision>,
+) -> Verdict<'a> {
+	let Some(pod) = paths.extract(document) else {
+		return Verdict::NoPodSpec;
+	};
+
+	if let Some(reason) = global_exemption(config, &pod, user_info) {
+		return Verdict::Exempt(reason);
+	}
+
+	let Some(group_config) = config.groups.get(group) else {
+		return Verdict::MissingGroupConfig;
+	};
+
+	if let Some(reason) = group_config.exemptions.as_ref().and_then(|e| e.exemption(&pod, user_info)) {
+		return Verdict::Exempt(format!("Exempt in group {group} since {reason}"));
+	}
+
+	let mode = config.group_mode(group_config);
+	let (overridden, mutation) = match group_config.with_namespace_overrides(namespace_annotations) {
+		Ok(overridden_config) => (
+			matches!(overridden_config, Cow::Owned(_)),
+			calculate_mutation(config, group, &overridden_config, document, paths, decisions),
+		),
+		Err(reason) => (false, Mutation::Deny(reason)),
+	};
+
+	Verdict::Mutate { group_config, mode, overridden, mutation }
+}
+
+/// Describes why the pod is exempt from every group, if it is
+fn global_exemption(config: &Config, pod: &Pod, user_info: &UserInfo) -> Option<String> {
+	if config.skip_daemon_sets {
+		let daemon_set = pod.metadata.owner_references.iter().flatten()
+			.find(|o| o.kind == "DaemonSet" && o.controller == Some(true));
+
+		if let Some(daemon_set) = daemon_set {
+			return Some(format!("Skipped since the pod is controlled by the DaemonSet {}", daemon_set.name));
+		}
+	}
+
+	config.exemptions.exemption(pod, user_info)
+		.map(|reason| format!("Exempt globally since {reason}"))
+}
+
+/// Allows the pod unchanged, recording why in the audit log
+fn exempt<K: Resource>(request: &AdmissionRequest<K>, reason: String) -> AdmissionResponse {
+	let mut response = AdmissionResponse::from(request);
+	response.audit_annotations.insert(EXEMPTION_AUDIT_ANNOTATION.to_string(), reason);
+	response
+}
+
+/// Outcome of applying a group's configuration to a pod
+pub enum Mutation {
+	Patch(Vec<PatchOperation>),
+	Deny(String),
+}
+
+/// Calculates the patches for the pod at `paths` in `document` in the given group
+///
+/// Every comparison made against the pod is recorded in `decisions`, explaining how the result was reached.
+fn calculate_mutation(
+	config: &Config,
+	group: &str,
+	group_config: &GroupConfig,
+	document: &Value,
+	paths: &PodPaths,
+	decisions: &mut Vec<Decision>,
+) -> Mutation {
+	let Some(pod) = paths.extract(document) else {
+		return Mutation::Deny(format!("The object has no valid pod spec at {}", paths.spec));
 	};
+	let pod_spec = pod.spec.as_ref().expect("Extracted pods always have a spec");
+	let spec_path = &paths.spec;
 
-	let pod_spec = request.object.as_ref()
-		.expect("Request object is missing")
-		.spec.as_ref()
-		.expect("Pod spec is missing");
+	if let Some(images_config) = &group_config.images {
+		if let Some(reason) = disallowed_images(pod_spec, images_config) {
+			return Mutation::Deny(reason);
+		}
+	}
 
 	let mut patches = Vec::new();
+	let mut annotations = BTreeMap::from([(GROUP_ANNOTATION.to_string(), group.to_string())]);
 
 	if let Some(node_selector_config) = &group_config.node_selector {
+		let node_selector_path = spec_path.join(&["nodeSelector"]);
 		let node_selector_patches = patch::calculate_node_selector_patches(
+			spec_path,
 			pod_spec,
 			node_selector_config,
 			&group_config.on_conflict,
+			decisions,
 		);
 
 		match node_selector_patches {
-			PatchResult::Allow(v) => patches.extend(v),
-			PatchResult::Deny { label, config_value, conflicting_value } => {
-				let reason = format!(
-					"The pod's nodeSelector {label}={conflicting_value} conflicts with pod-director's configuration {label}={config_value}"
-				);
-				return Ok(Json(AdmissionResponse::from(&request).deny(reason).into_review()));
+			PatchResult::Allow(v) => {
+				annotations.extend(node_selector_annotations(&node_selector_path, &v));
+				patches.extend(patch::minimise(document, &node_selector_path, v));
 			}
+			PatchResult::Deny(denial) => return Mutation::Deny(denial.to_string()),
 		}
 	}
 
 	if let Some(tolerations_config) = &group_config.tolerations {
-		let toleration_patches = patch::calculate_toleration_patches(pod_spec, tolerations_config);
-		patches.extend(toleration_patches);
+		let toleration_patches = patch::calculate_toleration_patches(spec_path, pod_spec, tolerations_config, decisions);
+		patches.extend(patch::minimise(document, &spec_path.join(&["tolerations"]), toleration_patches));
 	}
 
-	Ok(Json(
-		AdmissionResponse::from(&request)
-			.with_patch(json_patch::Patch(patches))
-			.unwrap()
-			.into_review(),
-	))
+	let scalars = [
+		("priorityClassName", pod_spec.priority_class_name.as_ref(), &group_config.priority_class_name),
+		("schedulerName", pod_spec.scheduler_name.as_ref().filter(|s| *s != DEFAULT_SCHEDULER), &group_config.scheduler_name),
+		("runtimeClassName", pod_spec.runtime_class_name.as_ref(), &group_config.runtime_class_name),
+	];
+
+	for (field, pod_value, config_value) in scalars {
+		let Some(config_value) = config_value else { continue };
+
+		match patch::calculate_scalar_patch(spec_path, field, pod_value, config_value, &group_config.on_conflict, decisions) {
+			PatchResult::Allow(v) => {
+				// The Priority and RuntimeClass admission controllers resolve the class into these before webhooks are called,
+				// so the values from the previous class must not be kept
+				let resolved = match field {
+					"priorityClassName" => vec![
+						("priority", pod_spec.priority.is_some()),
+						("preemptionPolicy", pod_spec.preemption_policy.is_some()),
+					],
+					"runtimeClassName" => vec![("overhead", pod_spec.overhead.is_some())],
+					_ => Vec::new(),
+				};
+				let changed = !v.is_empty();
+				patches.extend(v);
+
+				for (resolved_field, present) in resolved {
+					if changed && present {
+						patches.push(patch::remove(spec_path.join(&[resolved_field]).into()));
+					}
+				}
+			}
+			PatchResult::Deny(denial) => return Mutation::Deny(denial.to_string()),
+		}
+	}
+
+	if let Some(constraints_config) = &group_config.topology_spread_constraints {
+		let constraint_patches = patch::calculate_topology_spread_constraint_patches(
+			spec_path,
+			pod_spec,
+			constraints_config,
+			&group_config.on_conflict,
+			decisions,
+		);
+
+		match constraint_patches {
+			PatchResult::Allow(v) => {
+				patches.extend(patch::minimise(document, &spec_path.join(&["topologySpreadConstraints"]), v));
+			}
+			PatchResult::Deny(denial) => return Mutation::Deny(denial.to_string()),
+		}
+	}
+
+	if let Some(resources_config) = &group_config.resources {
+		patches.extend(patch::calculate_resource_patches(spec_path, pod_spec, resources_config, decisions));
+
+		if let Some(reason) = exceeded_requests(pod_spec, resources_config) {
+			return Mutation::Deny(reason);
+		}
+	}
+
+	if let Some(injections) = &group_config.injections {
+		patches.extend(patch::calculate_injection_patches(spec_path, pod_spec, injections, decisions));
+	}
+
+	// Pod specs embedded without their metadata, as in some custom resources, have nowhere to set labels and annotations
+	let Some(metadata_path) = &paths.metadata else {
+		return Mutation::Patch(patches);
+	};
+	let spec_patch_count = patches.len();
+
+	if let Some(labels_config) = &group_config.pod_labels {
+		let labels_path = metadata_path.join(&["labels"]);
+		let label_patches = patch::calculate_map_patches(
+			"labels",
+			labels_path.clone(),
+			pod.metadata.labels.as_ref(),
+			labels_config,
+			&group_config.on_conflict,
+			decisions,
+		);
+
+		match label_patches {
+			PatchResult::Allow(v) => patches.extend(patch::minimise(document, &labels_path, v)),
+			PatchResult::Deny(denial) => return Mutation::Deny(denial.to_string()),
+		}
+	}
+
+	let mut has_annotations = pod.metadata.annotations.is_some();
+
+	if let Some(annotations_config) = &group_config.pod_annotations {
+		let annotations_path = metadata_path.join(&["annotations"]);
+		let annotation_patches = patch::calculate_map_patches(
+			"annotations",
+			annotations_path.clone(),
+			pod.metadata.annotations.as_ref(),
+			annotations_config,
+			&group_config.on_conflict,
+			decisions,
+		);
+
+		match annotation_patches {
+			PatchResult::Allow(v) => {
+				has_annotations |= !v.is_empty();
+				patches.extend(patch::minimise(document, &annotations_path, v));
+			}
+			PatchResult::Deny(denial) => return Mutation::Deny(denial.to_string()),
+		}
+	}
+
+	if config.annotate_pods {
+		patches.extend(patch::calculate_annotation_patches(metadata_path, has_annotations, &annotations));
+	}
+
+	// Templates in custom resources may leave out their metadata entirely, which must exist before anything is added to it
+	if patches.len() > spec_patch_count && document.pointer(&metadata_path.to_string()).is_none() {
+		patches.insert(spec_patch_count, patch::add(metadata_path.to_string(), json!({})));
+	}
+
+	Mutation::Patch(patches)
+}
+
+/// Checks the pod's total requests, with defaults applied, against the configured maximums
+///
+/// As when scheduling, the total is the sum of every container's request, or the largest init container's, if greater.
+fn exceeded_requests(pod_spec: &PodSpec, resources_config: &ResourcesConfig) -> Option<String> {
+	let max_requests = resources_config.max_requests.as_ref()?;
+
+	let request = |container: &Container, resource: &String| container.resources.as_ref()
+		.and_then(|r| r.requests.as_ref())
+		.and_then(|r| r.get(resource))
+		.or_else(|| resources_config.default_requests.as_ref().and_then(|d| d.get(resource)))
+		.and_then(|q| quantity::parse(&q.0))
+		.unwrap_or_default();
+
+	for (resource, max) in max_requests {
+		let Some(parsed_max) = quantity::parse(&max.0) else { continue };
+
+		let containers_total: f64 = pod_spec.containers.iter().map(|c| request(c, resource)).sum();
+		let init_containers_max = pod_spec.init_containers.iter().flatten()
+			.map(|c| request(c, resource))
+			.fold(0.0, f64::max);
+		let total = containers_total.max(init_containers_max);
+
+		if total > parsed_max {
+			return Some(format!(
+				"The pod requests a total of {} {resource}, exceeding its group's maximum of {}",
+				quantity::format_like(total, &max.0), max.0,
+			));
+		}
+	}
+
+	None
+}
+
+/// Lists every container and init container whose image is not allowed by the group
+fn disallowed_images(pod_spec: &PodSpec, images_config: &ImagesConfig) -> Option<String> {
+	let offending: Vec<String> = pod_spec.init_containers.iter().flatten()
+		.chain(&pod_spec.containers)
+		.filter_map(|c| {
+			let image = c.image.as_deref().unwrap_or_default();
+			(!images_config.allows(image)).then(|| format!("{} ({image})", c.name))
+		})
+		.collect();
+
+	match offending.is_empty() {
+		true => None,
+		false => Some(format!("The pod uses images not allowed by its group: {}", offending.join(", "))),
+	}
+}
+
+/// Summarizes which nodeSelector labels were set or overridden by the given patches as pod annotations
+fn node_selector_annotations(node_selector_path: &Pointer, patches: &[PatchOperation]) -> BTreeMap<String, String> {
+	let node_selector_tokens = Pointer::parse(&node_selector_path.to_string()).unwrap_or_default();
+	let mut applied = Vec::new();
+	let mut overridden = Vec::new();
+
+	for operation in patches {
+		let (path, value, is_override) = match operation {
+			PatchOperation::Add(op) => (&op.path, &op.value, false),
+			PatchOperation::Replace(op) => (&op.path, &op.value, true),
+			_ => continue,
+		};
+
+		let tokens = Pointer::parse(path).unwrap_or_default();
+		if let (Some([label]), Some(value)) = (tokens.strip_prefix(node_selector_tokens.as_slice()), value.as_str()) {
+			applied.push(format!("{label}={value}"));
+			if is_override {
+				overridden.push(label.clone());
+			}
+		}
+	}
+
+	applied.sort();
+	overridden.sort();
+
+	let mut annotations = BTreeMap::new();
+	if !applied.is_empty() {
+		annotations.insert(APPLIED_NODE_SELECTOR_ANNOTATION.to_string(), applied.join(","));
+	}
+	if !overridden.is_empty() {
+		annotations.insert(OVERRIDDEN_KEYS_ANNOTATION.to_string(), overridden.join(","));
+	}
+
+	annotations
 }
 
 #[cfg(test)]
 mod tests {
-	use std::collections::HashMap;
+	use std::collections::BTreeMap;
 
 	use axum::body::Body;
 	use axum::http::{Request, StatusCode};
 	use axum::response::Response;
-	use k8s_openapi::api::core::v1::Toleration;
+	use http_body_util::BodyExt;
+	use k8s_openapi::api::core::v1::{Toleration, TopologySpreadConstraint};
+	use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 	use serde_json::json;
 	use tower::ServiceExt;
 
-	use crate::config::{Config, Conflict, GroupConfig};
+	use crate::config::{Config, Conflict, ExemptionsConfig, GroupConfig, ImagesConfig, Mode, ResourcesConfig};
 	use crate::server;
 	use crate::server::state::tests::TestAppState;
-	use crate::test_utils::{ParsedResponse, PodCreateRequestBuilder};
+	use crate::test_utils::{AdmissionCase, ParsedResponse, PodCreateRequestBuilder};
 	use crate::utils::patch;
 
 	async fn mutate_request(state: TestAppState, body: Body) -> Response {
@@ -103,6 +492,75 @@ mod tests {
 			.unwrap()
 	}
 
+	/// The namespace foo in the group bar, configured with `group_config`, replacing any groups in `config`
+	fn group_state(config: Config, group_config: GroupConfig) -> TestAppState {
+		let config = Config {
+			groups: BTreeMap::from([("bar".into(), group_config)]),
+			..config
+		};
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+		state
+	}
+
+	fn unannotated() -> Config {
+		Config { annotate_pods: false, ..Default::default() }
+	}
+
+	#[tokio::test]
+	async fn admission_cases_should_match_expectations() {
+		let cases = AdmissionCase::load_all();
+		assert!(!cases.is_empty());
+
+		let mut failures = Vec::new();
+		for case in cases {
+			if let Err(e) = case.run().await {
+				failures.push(e);
+			}
+		}
+
+		assert!(failures.is_empty(), "{} admission case(s) failed:\n{}", failures.len(), failures.join("\n"));
+	}
+
+	#[tokio::test]
+	async fn when_same_pod_is_mutated_repeatedly_should_produce_identical_responses() {
+		let mut bodies = Vec::new();
+
+		// Every run gets a freshly built configuration, as separate replicas would
+		for _ in 0..10 {
+			let config = Config {
+				groups: BTreeMap::from([
+					("bar".into(), GroupConfig {
+						node_selector: Some(BTreeMap::from([
+							("label-3".into(), "value-3".into()),
+							("label-0".into(), "value-0".into()),
+							("kubernetes.io/os".into(), "linux".into()),
+							("label-2".into(), "value-2".into()),
+							("label-1".into(), "value-1".into()),
+						])),
+						on_conflict: Conflict::Override,
+						..Default::default()
+					})
+				]),
+				..Default::default()
+			};
+			let mut state = TestAppState::new(config);
+			state.kubernetes.set_namespace_group("foo", "bar");
+
+			let body = PodCreateRequestBuilder::new()
+				.with_namespace("foo")
+				.with_annotation("existing", "annotation")
+				.with_node_selector("label-2", "conflicting-value")
+				.build();
+
+			let response = mutate_request(state, body).await;
+			bodies.push(response.into_body().collect().await.unwrap().to_bytes());
+		}
+
+		assert!(bodies.windows(2).all(|w| w[0] == w[1]));
+	}
+
 	#[tokio::test]
 	async fn when_pod_namespace_has_no_pd_label_should_allow_with_warning() {
 		let state = TestAppState::new(Config::default());
@@ -114,7 +572,7 @@ mod tests {
 
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 		assert_eq!(result.admission_response.warnings, Some(vec!["processed pod's namespace foo doesn't contain a pod-director group label, the MutatingWebhookConfiguration is probably misconfigured".to_owned()]))
 	}
 
@@ -131,7 +589,7 @@ mod tests {
 
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, false);
+		assert!(!result.admission_response.allowed);
 		assert_eq!(
 			result.admission_response.result.message,
 			"No pod-director group configured with the name bar, the namespace foo is misconfigured"
@@ -142,16 +600,18 @@ mod tests {
 	async fn when_pod_has_no_node_selector_should_insert_node_selector_and_pd_labels() {
 		let mut config = Config::default();
 		let group_config = GroupConfig {
-			node_selector: Some(HashMap::from([
+			node_selector: Some(BTreeMap::from([
 				("some-label".into(), "some-value".into())
 			])),
 			affinity: None,
 			tolerations: None,
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -163,11 +623,10 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 
 		let expected_patches = vec![
-			patch::add("/spec/nodeSelector".into(), json!({})),
-			patch::add("/spec/nodeSelector/some-label".into(), "some-value".into()),
+			patch::add("/spec/nodeSelector".into(), json!({"some-label": "some-value"})),
 		];
 
 		assert_eq!(result.patches, expected_patches);
@@ -177,16 +636,18 @@ mod tests {
 	async fn when_pod_has_existing_node_selector_not_matching_config_should_only_insert_pd_labels() {
 		let mut config = Config::default();
 		let group_config = GroupConfig {
-			node_selector: Some(HashMap::from([
+			node_selector: Some(BTreeMap::from([
 				("some-label".into(), "some-value".into())
 			])),
 			affinity: None,
 			tolerations: None,
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -199,7 +660,7 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 
 		let expected_patches = vec![
 			patch::add("/spec/nodeSelector/some-label".into(), "some-value".into())
@@ -209,10 +670,10 @@ mod tests {
 	}
 
 	#[tokio::test]
-	async fn when_pod_has_existing_node_selector_with_some_matching_config_should_only_insert_necessary_labels() {
+	async fn when_pod_has_existing_node_selector_with_some_matching_config_should_replace_it_with_merged_labels() {
 		let mut config = Config::default();
 		let group_config = GroupConfig {
-			node_selector: Some(HashMap::from([
+			node_selector: Some(BTreeMap::from([
 				("label-0".into(), "value-0".into()),
 				("label-1".into(), "value-1".into()),
 				("label-2".into(), "value-2".into())
@@ -220,10 +681,12 @@ mod tests {
 			affinity: None,
 			tolerations: None,
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -236,27 +699,36 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::replace("/spec/nodeSelector".into(), json!({
+				"label-0": "value-0",
+				"label-1": "value-1",
+				"label-2": "value-2",
+			})),
+		];
 
-		assert!(result.patches.contains(&patch::add("/spec/nodeSelector/label-0".into(), "value-0".into())));
-		assert!(result.patches.contains(&patch::add("/spec/nodeSelector/label-2".into(), "value-2".into())));
+		assert_eq!(result.patches, expected_patches);
 	}
 
 	#[tokio::test]
 	async fn when_pod_has_existing_node_selector_with_perfect_matching_config_should_do_nothing() {
 		let mut config = Config::default();
 		let group_config = GroupConfig {
-			node_selector: Some(HashMap::from([
+			node_selector: Some(BTreeMap::from([
 				("label-0".into(), "value-0".into()),
 				("label-1".into(), "value-1".into()),
 			])),
 			affinity: None,
 			tolerations: None,
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -270,7 +742,7 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 
 		assert!(result.patches.is_empty());
 	}
@@ -279,17 +751,19 @@ mod tests {
 	async fn when_pod_has_existing_node_selector_with_matching_config_and_extra_labels_should_do_nothing() {
 		let mut config = Config::default();
 		let group_config = GroupConfig {
-			node_selector: Some(HashMap::from([
+			node_selector: Some(BTreeMap::from([
 				("label-0".into(), "value-0".into()),
 				("label-1".into(), "value-1".into()),
 			])),
 			affinity: None,
 			tolerations: None,
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -304,7 +778,7 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 
 		assert!(result.patches.is_empty());
 	}
@@ -313,17 +787,19 @@ mod tests {
 	async fn when_pod_has_conflicting_node_selector_and_config_is_ignore_should_ignore_label() {
 		let mut config = Config::default();
 		let group_config = GroupConfig {
-			node_selector: Some(HashMap::from([
+			node_selector: Some(BTreeMap::from([
 				("label-0".into(), "value-0".into()),
 				("label-1".into(), "value-1".into()),
 			])),
 			affinity: None,
 			tolerations: None,
 			on_conflict: Conflict::Ignore,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -336,7 +812,7 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 
 		let expected_patches = vec![
 			patch::add("/spec/nodeSelector/label-1".into(), "value-1".into())
@@ -349,17 +825,19 @@ mod tests {
 	async fn when_pod_has_conflicting_node_selector_and_config_is_override_should_replace_label() {
 		let mut config = Config::default();
 		let group_config = GroupConfig {
-			node_selector: Some(HashMap::from([
+			node_selector: Some(BTreeMap::from([
 				("label-0".into(), "value-0".into()),
 				("label-1".into(), "value-1".into()),
 			])),
 			affinity: None,
 			tolerations: None,
 			on_conflict: Conflict::Override,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -372,27 +850,32 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 
-		assert!(result.patches.contains(&patch::replace("/spec/nodeSelector/label-0".into(), "value-0".into())));
-		assert!(result.patches.contains(&patch::add("/spec/nodeSelector/label-1".into(), "value-1".into())));
+		let expected_patches = vec![
+			patch::replace("/spec/nodeSelector".into(), json!({"label-0": "value-0", "label-1": "value-1"})),
+		];
+
+		assert_eq!(result.patches, expected_patches);
 	}
 
 	#[tokio::test]
 	async fn when_pod_has_conflicting_node_selector_and_config_is_reject_should_reject_pod() {
 		let mut config = Config::default();
 		let group_config = GroupConfig {
-			node_selector: Some(HashMap::from([
+			node_selector: Some(BTreeMap::from([
 				("label-0".into(), "value-0".into()),
 				("label-1".into(), "value-1".into()),
 			])),
 			affinity: None,
 			tolerations: None,
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -405,7 +888,7 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, false);
+		assert!(!result.admission_response.allowed);
 		assert_eq!(
 			result.admission_response.result.message,
 			"The pod's nodeSelector label-0=conflicting-value conflicts with pod-director's configuration label-0=value-0"
@@ -426,10 +909,12 @@ mod tests {
 				toleration_seconds: None,
 			}]),
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -441,16 +926,15 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 
 		let expected_patches = vec![
-			patch::add("/spec/tolerations".into(), json!([])),
-			patch::add("/spec/tolerations/-".into(), json!({
+			patch::add("/spec/tolerations".into(), json!([{
 				"key": "some-key",
 				"value": "some-value",
 				"operator": "Equals",
 				"effect": "NoSchedule"
-			})),
+			}])),
 		];
 
 		assert_eq!(result.patches, expected_patches);
@@ -470,10 +954,12 @@ mod tests {
 				toleration_seconds: None,
 			}]),
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -492,7 +978,7 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 
 		let expected_patches = vec![
 			patch::add("/spec/tolerations/-".into(), json!({
@@ -529,10 +1015,12 @@ mod tests {
 				},
 			]),
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -551,7 +1039,7 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
 
 		let expected_patches = vec![
 			patch::add("/spec/tolerations/-".into(), json!({
@@ -588,10 +1076,12 @@ mod tests {
 				},
 			]),
 			on_conflict: Conflict::Reject,
+			..Default::default()
 		};
-		config.groups = HashMap::from([
+		config.groups = BTreeMap::from([
 			("bar".into(), group_config)
 		]);
+		config.annotate_pods = false;
 
 		let mut state = TestAppState::new(config);
 		state.kubernetes.set_namespace_group("foo", "bar");
@@ -617,7 +1107,1506 @@ mod tests {
 		let response = mutate_request(state, body).await;
 		let result = ParsedResponse::from_response(response).await;
 		assert_eq!(result.status, StatusCode::OK);
-		assert_eq!(result.admission_response.allowed, true);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+	}
+
+	fn zone_constraint(max_skew: i32) -> TopologySpreadConstraint {
+		TopologySpreadConstraint {
+			topology_key: "topology.kubernetes.io/zone".into(),
+			max_skew,
+			when_unsatisfiable: "ScheduleAnyway".into(),
+			..Default::default()
+		}
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_no_topology_spread_constraints_should_insert_them() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			topology_spread_constraints: Some(vec![zone_constraint(1)]),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/topologySpreadConstraints".into(), json!([{
+				"topologyKey": "topology.kubernetes.io/zone",
+				"maxSkew": 1,
+				"whenUnsatisfiable": "ScheduleAnyway",
+			}])),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_topology_spread_constraint_for_other_key_should_append_constraint() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_topology_spread_constraint(TopologySpreadConstraint {
+				topology_key: "kubernetes.io/hostname".into(),
+				max_skew: 1,
+				when_unsatisfiable: "DoNotSchedule".into(),
+				..Default::default()
+			})
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			topology_spread_constraints: Some(vec![zone_constraint(1)]),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/topologySpreadConstraints/-".into(), json!({
+				"topologyKey": "topology.kubernetes.io/zone",
+				"maxSkew": 1,
+				"whenUnsatisfiable": "ScheduleAnyway",
+			})),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_matching_topology_spread_constraint_should_do_nothing() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_topology_spread_constraint(zone_constraint(1))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			topology_spread_constraints: Some(vec![zone_constraint(1)]),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_conflicting_topology_spread_constraint_and_config_is_ignore_should_keep_it() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_topology_spread_constraint(zone_constraint(3))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			topology_spread_constraints: Some(vec![zone_constraint(1)]),
+			on_conflict: Conflict::Ignore,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_conflicting_topology_spread_constraint_and_config_is_override_should_replace_it() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_topology_spread_constraint(TopologySpreadConstraint {
+				topology_key: "kubernetes.io/hostname".into(),
+				max_skew: 1,
+				when_unsatisfiable: "DoNotSchedule".into(),
+				..Default::default()
+			})
+			.with_topology_spread_constraint(zone_constraint(3))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			topology_spread_constraints: Some(vec![zone_constraint(1)]),
+			on_conflict: Conflict::Override,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::replace("/spec/topologySpreadConstraints/1".into(), json!({
+				"topologyKey": "topology.kubernetes.io/zone",
+				"maxSkew": 1,
+				"whenUnsatisfiable": "ScheduleAnyway",
+			})),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_conflicting_topology_spread_constraint_and_config_is_reject_should_reject_pod() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_topology_spread_constraint(zone_constraint(3))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			topology_spread_constraints: Some(vec![zone_constraint(1)]),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			concat!(
+				r#"The pod's topologySpreadConstraints topology.kubernetes.io/zone={"maxSkew":3,"topologyKey":"topology.kubernetes.io/zone","whenUnsatisfiable":"ScheduleAnyway"} "#,
+				r#"conflicts with pod-director's configuration topology.kubernetes.io/zone={"maxSkew":1,"topologyKey":"topology.kubernetes.io/zone","whenUnsatisfiable":"ScheduleAnyway"}"#,
+			)
+		);
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_no_scalar_fields_should_set_them_and_clear_resolved_priority() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			priority_class_name: Some("batch".into()),
+			scheduler_name: Some("batch-scheduler".into()),
+			runtime_class_name: Some("gvisor".into()),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/priorityClassName".into(), "batch".into()),
+			patch::remove("/spec/priority".into()),
+			patch::remove("/spec/preemptionPolicy".into()),
+			patch::add("/spec/schedulerName".into(), "batch-scheduler".into()),
+			patch::add("/spec/runtimeClassName".into(), "gvisor".into()),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_matching_scalar_fields_should_do_nothing() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_priority_class_name("batch")
+			.with_scheduler_name("batch-scheduler")
+			.with_runtime_class_name("gvisor")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			priority_class_name: Some("batch".into()),
+			scheduler_name: Some("batch-scheduler".into()),
+			runtime_class_name: Some("gvisor".into()),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
 		assert!(result.patches.is_empty());
 	}
+
+	#[tokio::test]
+	async fn when_pod_has_conflicting_scalar_fields_and_config_is_ignore_should_keep_them() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_priority_class_name("critical")
+			.with_scheduler_name("other-scheduler")
+			.with_runtime_class_name("runc")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			priority_class_name: Some("batch".into()),
+			scheduler_name: Some("batch-scheduler".into()),
+			runtime_class_name: Some("gvisor".into()),
+			on_conflict: Conflict::Ignore,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_conflicting_scalar_fields_and_config_is_override_should_replace_them() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_priority_class_name("critical")
+			.with_scheduler_name("other-scheduler")
+			.with_runtime_class_name("runc")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			priority_class_name: Some("batch".into()),
+			scheduler_name: Some("batch-scheduler".into()),
+			runtime_class_name: Some("gvisor".into()),
+			on_conflict: Conflict::Override,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::replace("/spec/priorityClassName".into(), "batch".into()),
+			patch::remove("/spec/priority".into()),
+			patch::remove("/spec/preemptionPolicy".into()),
+			patch::replace("/spec/schedulerName".into(), "batch-scheduler".into()),
+			patch::replace("/spec/runtimeClassName".into(), "gvisor".into()),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_pod_runtime_class_is_overridden_should_clear_resolved_overhead() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_priority_class_name("batch")
+			.with_scheduler_name("batch-scheduler")
+			.with_runtime_class_name("runc")
+			.with_overhead(json!({"cpu": "250m", "memory": "120Mi"}))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			priority_class_name: Some("batch".into()),
+			scheduler_name: Some("batch-scheduler".into()),
+			runtime_class_name: Some("gvisor".into()),
+			on_conflict: Conflict::Override,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::replace("/spec/runtimeClassName".into(), "gvisor".into()),
+			patch::remove("/spec/overhead".into()),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_conflicting_scalar_field_and_config_is_reject_should_reject_pod() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_runtime_class_name("runc")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			priority_class_name: Some("batch".into()),
+			scheduler_name: Some("batch-scheduler".into()),
+			runtime_class_name: Some("gvisor".into()),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			"The pod's runtimeClassName runc conflicts with pod-director's configuration gvisor"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_pod_lacks_group_labels_and_annotations_should_insert_them() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_annotation("existing", "annotation")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			pod_labels: Some(BTreeMap::from([
+				("example.com/cost-center".into(), "batch".into()),
+			])),
+			pod_annotations: Some(BTreeMap::from([
+				("cluster-autoscaler.kubernetes.io/safe-to-evict".into(), "false".into()),
+			])),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/metadata/labels/example.com~1cost-center".into(), "batch".into()),
+			patch::add("/metadata/annotations/cluster-autoscaler.kubernetes.io~1safe-to-evict".into(), "false".into()),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_no_annotations_should_add_group_annotations_alongside_pd_annotations() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let state = group_state(Config { annotate_pods: true, ..Default::default() }, GroupConfig {
+			pod_labels: Some(BTreeMap::from([
+				("example.com/cost-center".into(), "batch".into()),
+			])),
+			pod_annotations: Some(BTreeMap::from([
+				("cluster-autoscaler.kubernetes.io/safe-to-evict".into(), "false".into()),
+			])),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/metadata/labels/example.com~1cost-center".into(), "batch".into()),
+			patch::add("/metadata/annotations".into(), json!({"cluster-autoscaler.kubernetes.io/safe-to-evict": "false"})),
+			patch::add("/metadata/annotations/pod-director~1group".into(), "bar".into()),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_conflicting_annotation_and_config_is_override_should_replace_it() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_annotation("cluster-autoscaler.kubernetes.io/safe-to-evict", "true")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			pod_labels: Some(BTreeMap::from([
+				("example.com/cost-center".into(), "batch".into()),
+			])),
+			pod_annotations: Some(BTreeMap::from([
+				("cluster-autoscaler.kubernetes.io/safe-to-evict".into(), "false".into()),
+			])),
+			on_conflict: Conflict::Override,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.contains(
+			&patch::replace("/metadata/annotations/cluster-autoscaler.kubernetes.io~1safe-to-evict".into(), "false".into())
+		));
+	}
+
+	#[tokio::test]
+	async fn when_pod_has_conflicting_annotation_and_config_is_reject_should_reject_pod() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_annotation("cluster-autoscaler.kubernetes.io/safe-to-evict", "true")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			pod_labels: Some(BTreeMap::from([
+				("example.com/cost-center".into(), "batch".into()),
+			])),
+			pod_annotations: Some(BTreeMap::from([
+				("cluster-autoscaler.kubernetes.io/safe-to-evict".into(), "false".into()),
+			])),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			"The pod's annotations cluster-autoscaler.kubernetes.io/safe-to-evict=true conflicts with pod-director's configuration cluster-autoscaler.kubernetes.io/safe-to-evict=false"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_containers_have_no_resources_should_insert_defaults() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_init_container(json!({"name": "init", "image": "alpine"}))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			resources: Some(ResourcesConfig {
+				default_requests: Some(BTreeMap::from([
+					("cpu".into(), Quantity("100m".into())),
+					("memory".into(), Quantity("128Mi".into())),
+				])),
+				default_limits: Some(BTreeMap::from([
+					("memory".into(), Quantity("256Mi".into())),
+				])),
+				max_requests: None,
+			}),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/containers/0/resources/requests".into(), json!({"cpu": "100m", "memory": "128Mi"})),
+			patch::add("/spec/containers/0/resources/limits".into(), json!({"memory": "256Mi"})),
+			patch::add("/spec/initContainers/0/resources".into(), json!({
+				"requests": {"cpu": "100m", "memory": "128Mi"},
+				"limits": {"memory": "256Mi"},
+			})),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_container_has_some_resources_should_only_insert_missing_defaults() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_resources(json!({
+				"requests": {"cpu": "500m"},
+				"limits": {"cpu": "1"},
+			}))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			resources: Some(ResourcesConfig {
+				default_requests: Some(BTreeMap::from([
+					("cpu".into(), Quantity("100m".into())),
+					("memory".into(), Quantity("128Mi".into())),
+				])),
+				default_limits: Some(BTreeMap::from([
+					("memory".into(), Quantity("256Mi".into())),
+				])),
+				max_requests: None,
+			}),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/containers/0/resources/requests/memory".into(), "128Mi".into()),
+			patch::add("/spec/containers/0/resources/limits/memory".into(), "256Mi".into()),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_container_requests_more_than_default_limit_should_not_insert_limit() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_resources(json!({"requests": {"cpu": "100m", "memory": "1Gi"}}))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			resources: Some(ResourcesConfig {
+				default_requests: Some(BTreeMap::from([
+					("cpu".into(), Quantity("100m".into())),
+					("memory".into(), Quantity("128Mi".into())),
+				])),
+				default_limits: Some(BTreeMap::from([
+					("memory".into(), Quantity("256Mi".into())),
+				])),
+				max_requests: None,
+			}),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+	}
+
+	#[tokio::test]
+	async fn when_pod_requests_more_than_maximum_should_reject_pod() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_resources(json!({"requests": {"cpu": "3"}}))
+			.with_init_container(json!({"name": "init", "image": "alpine", "resources": {"requests": {"cpu": "5"}}}))
+			.build();
+
+		let max_requests = BTreeMap::from([("cpu".into(), Quantity("4".into()))]);
+		let state = group_state(unannotated(), GroupConfig {
+			resources: Some(ResourcesConfig {
+				default_requests: Some(BTreeMap::from([
+					("cpu".into(), Quantity("100m".into())),
+					("memory".into(), Quantity("128Mi".into())),
+				])),
+				default_limits: Some(BTreeMap::from([
+					("memory".into(), Quantity("256Mi".into())),
+				])),
+				max_requests: Some(max_requests),
+			}),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			"The pod requests a total of 5 cpu, exceeding its group's maximum of 4"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_pod_requests_within_maximum_after_defaults_should_allow_pod() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_resources(json!({"requests": {"cpu": "3", "memory": "1Gi"}}))
+			.with_init_container(json!({"name": "init", "image": "alpine"}))
+			.build();
+
+		let max_requests = BTreeMap::from([
+			("cpu".into(), Quantity("4".into())),
+			("memory".into(), Quantity("1200Mi".into())),
+		]);
+		let state = group_state(unannotated(), GroupConfig {
+			resources: Some(ResourcesConfig {
+				default_requests: Some(BTreeMap::from([
+					("cpu".into(), Quantity("100m".into())),
+					("memory".into(), Quantity("128Mi".into())),
+				])),
+				default_limits: Some(BTreeMap::from([
+					("memory".into(), Quantity("256Mi".into())),
+				])),
+				max_requests: Some(max_requests),
+			}),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+	}
+
+	#[tokio::test]
+	async fn when_default_requests_push_pod_over_maximum_should_reject_pod() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_resources(json!({"requests": {"cpu": "3"}}))
+			.build();
+
+		let max_requests = BTreeMap::from([("memory".into(), Quantity("100Mi".into()))]);
+		let state = group_state(unannotated(), GroupConfig {
+			resources: Some(ResourcesConfig {
+				default_requests: Some(BTreeMap::from([
+					("cpu".into(), Quantity("100m".into())),
+					("memory".into(), Quantity("128Mi".into())),
+				])),
+				default_limits: Some(BTreeMap::from([
+					("memory".into(), Quantity("256Mi".into())),
+				])),
+				max_requests: Some(max_requests),
+			}),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			"The pod requests a total of 128Mi memory, exceeding its group's maximum of 100Mi"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_injection_matches_container_should_insert_missing_entries() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			injections: Some(vec![serde_json::from_value(json!({
+				"containers": ["test"],
+				"env": [{"name": "NVIDIA_VISIBLE_DEVICES", "value": "all"}],
+				"volumeMounts": [{"name": "nvidia-driver", "mountPath": "/usr/local/nvidia", "readOnly": true}],
+				"volumes": [{"name": "nvidia-driver", "hostPath": {"path": "/home/kubernetes/bin/nvidia"}}],
+			})).unwrap()]),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/containers/0/env".into(), json!([{"name": "NVIDIA_VISIBLE_DEVICES", "value": "all"}])),
+			patch::add("/spec/containers/0/volumeMounts/-".into(), json!({
+				"name": "nvidia-driver",
+				"mountPath": "/usr/local/nvidia",
+				"readOnly": true,
+			})),
+			patch::add("/spec/volumes/-".into(), json!({"name": "nvidia-driver", "hostPath": {"path": "/home/kubernetes/bin/nvidia"}})),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_container_already_has_injected_entries_should_keep_them() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_env("NVIDIA_VISIBLE_DEVICES", "none")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			injections: Some(vec![serde_json::from_value(json!({
+				"env": [{"name": "NVIDIA_VISIBLE_DEVICES", "value": "all"}],
+				"volumeMounts": [{"name": "nvidia-driver", "mountPath": "/usr/local/nvidia", "readOnly": true}],
+				"volumes": [{"name": "nvidia-driver", "hostPath": {"path": "/home/kubernetes/bin/nvidia"}}],
+			})).unwrap()]),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/containers/0/volumeMounts/-".into(), json!({
+				"name": "nvidia-driver",
+				"mountPath": "/usr/local/nvidia",
+				"readOnly": true,
+			})),
+			patch::add("/spec/volumes/-".into(), json!({"name": "nvidia-driver", "hostPath": {"path": "/home/kubernetes/bin/nvidia"}})),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_injection_matches_init_container_should_insert_missing_entries() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_init_container(json!({"name": "init", "image": "busybox"}))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			injections: Some(vec![serde_json::from_value(json!({
+				"containers": ["init"],
+				"env": [{"name": "NVIDIA_VISIBLE_DEVICES", "value": "all"}],
+				"volumeMounts": [{"name": "nvidia-driver", "mountPath": "/usr/local/nvidia", "readOnly": true}],
+				"volumes": [{"name": "nvidia-driver", "hostPath": {"path": "/home/kubernetes/bin/nvidia"}}],
+			})).unwrap()]),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/initContainers/0/env".into(), json!([{"name": "NVIDIA_VISIBLE_DEVICES", "value": "all"}])),
+			patch::add("/spec/initContainers/0/volumeMounts".into(), json!([{
+				"name": "nvidia-driver",
+				"mountPath": "/usr/local/nvidia",
+				"readOnly": true,
+			}])),
+			patch::add("/spec/volumes/-".into(), json!({"name": "nvidia-driver", "hostPath": {"path": "/home/kubernetes/bin/nvidia"}})),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_injection_does_not_match_any_container_should_do_nothing() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			injections: Some(vec![serde_json::from_value(json!({
+				"containers": ["other"],
+				"env": [{"name": "NVIDIA_VISIBLE_DEVICES", "value": "all"}],
+				"volumeMounts": [{"name": "nvidia-driver", "mountPath": "/usr/local/nvidia", "readOnly": true}],
+				"volumes": [{"name": "nvidia-driver", "hostPath": {"path": "/home/kubernetes/bin/nvidia"}}],
+			})).unwrap()]),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+	}
+
+	#[tokio::test]
+	async fn when_images_are_allowed_should_mutate_pod() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_init_container(json!({"name": "init", "image": "docker.io/library/busybox:1.36"}))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("kubernetes.io/arch".into(), "arm64".into()),
+			])),
+			images: Some(ImagesConfig { allowed: Some(vec!["docker.io/*".into()]), denied: Some(vec!["*-amd64".into()]) }),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert_eq!(result.patches, vec![
+			patch::add("/spec/nodeSelector".into(), json!({"kubernetes.io/arch": "arm64"})),
+		]);
+	}
+
+	#[tokio::test]
+	async fn when_images_are_not_allowed_should_reject_pod_listing_them() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_init_container(json!({"name": "init", "image": "registry.example.com/setup:1.0"}))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("kubernetes.io/arch".into(), "arm64".into()),
+			])),
+			images: Some(ImagesConfig { allowed: Some(vec!["registry.example.com/*".into()]), denied: None }),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			"The pod uses images not allowed by its group: test (alpine)"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_images_are_denied_should_reject_pod_even_if_allowed() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_init_container(json!({"name": "init", "image": "registry.example.com/setup:1.0-amd64"}))
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("kubernetes.io/arch".into(), "arm64".into()),
+			])),
+			images: Some(ImagesConfig {
+				allowed: Some(vec!["registry.example.com/*".into(), "alpine".into()]),
+				denied: Some(vec!["*-amd64".into()]),
+			}),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			"The pod uses images not allowed by its group: init (registry.example.com/setup:1.0-amd64)"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_pod_matches_global_exemption_should_allow_it_unchanged_even_if_group_is_missing() {
+		let exemptions = ExemptionsConfig {
+			pod_labels: Some(BTreeMap::from([("pod-director/exempt".into(), "true".into())])),
+			..Default::default()
+		};
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("misconfigured")
+			.with_label("pod-director/exempt", "true")
+			.build();
+
+		let mut state = group_state(Config { exemptions, ..unannotated() }, GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			..Default::default()
+		});
+		state.kubernetes.set_namespace_group("misconfigured", "missing");
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(
+			result.admission_response.audit_annotations["exemption"],
+			"Exempt globally since the pod has the label pod-director/exempt=true"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_pod_matches_group_exemption_should_allow_it_unchanged() {
+		let group_exemptions = ExemptionsConfig {
+			service_accounts: Some(vec!["gitlab-runner".into()]),
+			..Default::default()
+		};
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_service_account_name("gitlab-runner")
+			.with_node_selector("label-0", "conflicting-value")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			exemptions: Some(group_exemptions),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(
+			result.admission_response.audit_annotations["exemption"],
+			"Exempt in group bar since the pod runs as the service account gitlab-runner"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_requesting_user_is_exempt_should_allow_pod_unchanged() {
+		let exemptions = ExemptionsConfig {
+			user_groups: Some(vec!["system:masters".into()]),
+			..Default::default()
+		};
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let state = group_state(Config { exemptions, ..unannotated() }, GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(
+			result.admission_response.audit_annotations["exemption"],
+			"Exempt globally since the pod was created by a member of the group system:masters"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_pod_matches_no_exemption_should_mutate_it() {
+		let exemptions = ExemptionsConfig {
+			pod_labels: Some(BTreeMap::from([("run".into(), "other".into())])),
+			users: Some(vec!["admin".into()]),
+			..Default::default()
+		};
+		let group_exemptions = ExemptionsConfig {
+			service_accounts: Some(vec!["gitlab-runner".into()]),
+			owner_kinds: Some(vec!["DaemonSet".into()]),
+			..Default::default()
+		};
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let state = group_state(Config { exemptions, ..unannotated() }, GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			exemptions: Some(group_exemptions),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert!(result.admission_response.allowed);
+		assert!(result.admission_response.audit_annotations.is_empty());
+		assert_eq!(result.patches, vec![
+			patch::add("/spec/nodeSelector".into(), json!({"label-0": "value-0"})),
+		]);
+	}
+
+	#[tokio::test]
+	async fn when_pod_is_controlled_by_daemon_set_should_allow_it_unchanged() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_owner_reference("apps/v1", "DaemonSet", "node-exporter")
+			.build();
+
+		let state = group_state(Config { skip_daemon_sets: true, ..unannotated() }, GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(
+			result.admission_response.audit_annotations["exemption"],
+			"Skipped since the pod is controlled by the DaemonSet node-exporter"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_daemon_sets_are_not_skipped_should_mutate_daemon_set_pod() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_owner_reference("apps/v1", "DaemonSet", "node-exporter")
+			.build();
+
+		let state = group_state(Config { skip_daemon_sets: false, ..unannotated() }, GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert!(result.admission_response.allowed);
+		assert_eq!(result.patches, vec![
+			patch::add("/spec/nodeSelector".into(), json!({"label-0": "value-0"})),
+		]);
+	}
+
+	#[tokio::test]
+	async fn when_pod_is_controlled_by_other_kind_should_mutate_it() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_owner_reference("apps/v1", "ReplicaSet", "app-5d8f7c9b4")
+			.build();
+
+		let state = group_state(Config { skip_daemon_sets: true, ..unannotated() }, GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert!(result.admission_response.allowed);
+		assert!(result.admission_response.audit_annotations.is_empty());
+		assert_eq!(result.patches, vec![
+			patch::add("/spec/nodeSelector".into(), json!({"label-0": "value-0"})),
+		]);
+	}
+
+	#[tokio::test]
+	async fn when_pod_owner_kind_is_exempt_in_group_should_allow_it_unchanged() {
+		let group_exemptions = ExemptionsConfig {
+			owner_kinds: Some(vec!["Node".into()]),
+			..Default::default()
+		};
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_owner_reference("v1", "Node", "node-1")
+			.build();
+
+		let state = group_state(unannotated(), GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			exemptions: Some(group_exemptions),
+			..Default::default()
+		});
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(
+			result.admission_response.audit_annotations["exemption"],
+			"Exempt in group bar since the pod is owned by the Node node-1"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_namespace_extends_allowed_field_should_apply_both_values() {
+		let mut state = group_state(unannotated(), GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			tolerations: Some(vec![Toleration {
+				key: Some("role".into()),
+				operator: Some("Exists".into()),
+				..Default::default()
+			}]),
+			allow_namespace_overrides: Some(vec!["tolerations".into()]),
+			..Default::default()
+		});
+		state.kubernetes.set_namespace_annotation("foo", "pod-director/tolerations", r#"[{"key": "gpu", "operator": "Exists", "effect": "NoSchedule"}]"#);
+		state.kubernetes.set_namespace_annotation("foo", "unrelated.example.com/owner", "team-a");
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert_eq!(result.patches, vec![
+			patch::add("/spec/nodeSelector".into(), json!({"label-0": "value-0"})),
+			patch::add("/spec/tolerations".into(), json!([
+				{"key": "role", "operator": "Exists"},
+				{"key": "gpu", "operator": "Exists", "effect": "NoSchedule"},
+			])),
+		]);
+	}
+
+	#[tokio::test]
+	async fn when_namespace_overrides_field_not_allowed_by_group_should_reject_pod() {
+		let mut state = group_state(unannotated(), GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			tolerations: Some(vec![Toleration {
+				key: Some("role".into()),
+				operator: Some("Exists".into()),
+				..Default::default()
+			}]),
+			allow_namespace_overrides: Some(vec!["tolerations".into()]),
+			..Default::default()
+		});
+		state.kubernetes.set_namespace_annotation("foo", "pod-director/nodeSelector", "label-0: other");
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			"The namespace's annotation pod-director/nodeSelector overrides nodeSelector, which its group doesn't allow"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_namespace_override_is_invalid_should_reject_pod() {
+		let mut state = group_state(unannotated(), GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			tolerations: Some(vec![Toleration {
+				key: Some("role".into()),
+				operator: Some("Exists".into()),
+				..Default::default()
+			}]),
+			allow_namespace_overrides: Some(vec!["tolerations".into()]),
+			..Default::default()
+		});
+		state.kubernetes.set_namespace_annotation("foo", "pod-director/tolerations", "- key: gpu\n  operator: Maybe");
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			r#"The namespace's overrides are invalid: invalid value for "namespaceOverrides.tolerations[0].operator": "Maybe" is not a valid operator, must be either "Equal" or "Exists""#
+		);
+	}
+
+	#[tokio::test]
+	async fn when_namespace_override_cannot_be_parsed_should_reject_pod() {
+		let mut state = group_state(unannotated(), GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			tolerations: Some(vec![Toleration {
+				key: Some("role".into()),
+				operator: Some("Exists".into()),
+				..Default::default()
+			}]),
+			allow_namespace_overrides: Some(vec!["tolerations".into()]),
+			..Default::default()
+		});
+		state.kubernetes.set_namespace_annotation("foo", "pod-director/tolerations", "not a list");
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert!(!result.admission_response.allowed);
+		assert!(result.admission_response.result.message.starts_with("The namespace's annotation pod-director/tolerations is invalid: "));
+	}
+
+	#[tokio::test]
+	async fn when_pod_is_mutated_and_has_no_annotations_should_insert_annotations_with_changes() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+				("label-1".into(), "value-1".into()),
+			])),
+			affinity: None,
+			tolerations: None,
+			on_conflict: Conflict::Override,
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_node_selector("label-0", "conflicting-value")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		assert!(result.patches.contains(&patch::add("/metadata/annotations".into(), json!({
+			"pod-director/group": "bar",
+			"pod-director/applied-node-selector": "label-0=value-0,label-1=value-1",
+			"pod-director/overridden-keys": "label-0",
+		}))));
+	}
+
+	#[tokio::test]
+	async fn when_pod_is_mutated_and_has_existing_annotations_should_insert_only_pd_annotations() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			affinity: None,
+			tolerations: None,
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_annotation("existing", "annotation")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/nodeSelector".into(), json!({"label-0": "value-0"})),
+			patch::add("/metadata/annotations/pod-director~1applied-node-selector".into(), "label-0=value-0".into()),
+			patch::add("/metadata/annotations/pod-director~1group".into(), "bar".into()),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_pod_requires_no_changes_should_only_annotate_group() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into()),
+			])),
+			affinity: None,
+			tolerations: None,
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_node_selector("label-0", "value-0")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/metadata/annotations".into(), json!({"pod-director/group": "bar"})),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+	}
+
+	#[tokio::test]
+	async fn when_group_is_in_dry_run_mode_should_allow_without_patches_and_warn_about_them() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("some-label".into(), "some-value".into())
+			])),
+			mode: Some(Mode::DryRun),
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+		config.annotate_pods = false;
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+		let metrics = state.metrics.clone();
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(result.admission_response.warnings, Some(vec![
+			r#"pod-director dry run for group bar: would apply patch {"op":"add","path":"/spec/nodeSelector","value":{"some-label":"some-value"}}"#.to_owned(),
+		]));
+		assert_eq!(metrics.admissions("bar", Mode::DryRun, true), 1);
+		assert_eq!(metrics.patches("bar", Mode::DryRun), 1);
+		assert_eq!(metrics.admissions("bar", Mode::Enforce, true), 0);
+	}
+
+	#[tokio::test]
+	async fn when_group_is_in_dry_run_mode_and_pod_conflicts_should_allow_and_warn_about_denial() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into())
+			])),
+			on_conflict: Conflict::Reject,
+			mode: Some(Mode::DryRun),
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+		let metrics = state.metrics.clone();
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_node_selector("label-0", "conflicting-value")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(result.admission_response.warnings, Some(vec![
+			"pod-director dry run for group bar: would deny pod: The pod's nodeSelector label-0=conflicting-value conflicts with pod-director's configuration label-0=value-0".to_owned(),
+		]));
+		assert_eq!(metrics.admissions("bar", Mode::DryRun, false), 1);
+	}
+
+	#[tokio::test]
+	async fn when_global_mode_is_dry_run_and_group_is_enforced_should_patch_pod() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("some-label".into(), "some-value".into())
+			])),
+			mode: Some(Mode::Enforce),
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+		config.mode = Mode::DryRun;
+		config.annotate_pods = false;
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+		let metrics = state.metrics.clone();
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert_eq!(result.admission_response.warnings, None);
+		assert_eq!(result.patches.len(), 1);
+		assert_eq!(metrics.admissions("bar", Mode::Enforce, true), 1);
+		assert_eq!(metrics.patches("bar", Mode::Enforce), 1);
+	}
+
+	#[tokio::test]
+	async fn when_global_mode_is_dry_run_should_apply_to_groups_without_mode() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("some-label".into(), "some-value".into())
+			])),
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+		config.mode = Mode::DryRun;
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(result.admission_response.warnings.map(|w| w.len()), Some(2));
+	}
+
+	#[tokio::test]
+	async fn when_request_is_dry_run_should_patch_but_not_record_metrics() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("some-label".into(), "some-value".into())
+			])),
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+		config.annotate_pods = false;
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+		let metrics = state.metrics.clone();
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_dry_run(true)
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+
+		let expected_patches = vec![
+			patch::add("/spec/nodeSelector".into(), json!({"some-label": "some-value"})),
+		];
+
+		assert_eq!(result.patches, expected_patches);
+		assert_eq!(metrics.admissions("bar", Mode::Enforce, true), 0);
+		assert_eq!(metrics.patches("bar", Mode::Enforce), 0);
+	}
+
+	#[tokio::test]
+	async fn when_request_is_dry_run_and_pod_is_denied_should_not_record_metrics() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into())
+			])),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+		let metrics = state.metrics.clone();
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_node_selector("label-0", "conflicting-value")
+			.with_dry_run(true)
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(metrics.admissions("bar", Mode::Enforce, false), 0);
+	}
+
+	#[tokio::test]
+	async fn when_request_is_not_dry_run_should_record_metrics() {
+		let mut config = Config::default();
+		let group_config = GroupConfig {
+			node_selector: Some(BTreeMap::from([
+				("label-0".into(), "value-0".into())
+			])),
+			on_conflict: Conflict::Reject,
+			..Default::default()
+		};
+		config.groups = BTreeMap::from([
+			("bar".into(), group_config)
+		]);
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+		let metrics = state.metrics.clone();
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_node_selector("label-0", "conflicting-value")
+			.with_dry_run(false)
+			.build();
+
+		let response = mutate_request(state, body).await;
+		let result = ParsedResponse::from_response(response).await;
+		assert!(!result.admission_response.allowed);
+		assert_eq!(metrics.admissions("bar", Mode::Enforce, false), 1);
+	}
 }
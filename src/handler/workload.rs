@@ -0,0 +1,327 @@
+use axum::extract::State;
+use axum::Json;
+use axum::response::Result;
+use kube::api::DynamicObject;
+use kube::core::admission::{AdmissionRequest, AdmissionResponse, AdmissionReview, Operation};
+
+use crate::config::Config;
+use crate::error::ResponseError;
+use crate::handler::mutate::admit;
+use crate::server::AppState;
+use crate::utils::patch::{PodPaths, Pointer};
+
+/// Mutates the pod templates of workloads and configured custom resources, so their manifests show where their pods will run
+///
+/// Pods created from mutated templates are still admitted by `mutate`, which then finds nothing left to change.
+pub async fn mutate_workload<S: AppState>(
+	State(app_state): State<S>,
+	Json(body): Json<AdmissionReview<DynamicObject>>,
+) -> Result<Json<AdmissionReview<DynamicObject>>, ResponseError> {
+	let request: AdmissionRequest<DynamicObject> = body.try_into()?;
+
+	let Some(paths) = pod_paths(app_state.config(), &request.kind.group, &request.kind.kind) else {
+		return Err(ResponseError::UnsupportedKind {
+			kind: request.kind.kind.clone(),
+			response: AdmissionResponse::from(&request),
+		});
+	};
+
+	// Mutating an untouched template would roll workloads out on unrelated updates after a configuration change, or be
+	// rejected outright for Jobs, whose template is immutable
+	if request.operation == Operation::Update && !template_changed(&request, &paths) {
+		return Ok(Json(AdmissionResponse::from(&request).into_review()));
+	}
+
+	let response = admit(&app_state, &request, &paths).await?;
+
+	Ok(Json(response.into_review()))
+}
+
+fn template_changed(request: &AdmissionRequest<DynamicObject>, paths: &PodPaths) -> bool {
+	let (Some(object), Some(old_object)) = (&request.object, &request.old_object) else {
+		return true;
+	};
+	let document = serde_json::to_value(object).expect("Admitted objects should always be serializable");
+	let old_document = serde_json::to_value(old_object).expect("Admitted objects should always be serializable");
+
+	paths.metadata.iter().chain([&paths.spec])
+		.map(ToString::to_string)
+		.any(|pointer| document.pointer(&pointer) != old_document.pointer(&pointer))
+}
+
+fn pod_paths(config: &Config, group: &str, kind: &str) -> Option<PodPaths> {
+	match (group, kind) {
+		("apps", "Deployment" | "StatefulSet") | ("batch", "Job") => Some(PodPaths::template(Pointer::new(&["spec", "template"]))),
+		("batch", "CronJob") => Some(PodPaths::template(Pointer::new(&["spec", "jobTemplate", "spec", "template"]))),
+		_ => config.custom_resources.iter()
+			.find(|r| r.group == group && r.kind == kind)
+			.and_then(|r| r.pod_paths()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use axum::body::Body;
+	use axum::http::{Request, StatusCode};
+	use axum::response::Response;
+	use serde_json::{json, Value};
+	use tower::ServiceExt;
+
+	use crate::config::{Config, Conflict, CustomResourceConfig, GroupConfig};
+	use crate::server;
+	use crate::server::state::tests::TestAppState;
+	use crate::test_utils::{ParsedResponse, PodCreateRequestBuilder};
+	use crate::utils::patch;
+
+	async fn mutate_workload_request(body: Body) -> Response {
+		let config = Config {
+			groups: BTreeMap::from([
+				("bar".into(), GroupConfig {
+					node_selector: Some(BTreeMap::from([
+						("label-0".into(), "value-0".into()),
+					])),
+					on_conflict: Conflict::Reject,
+					..Default::default()
+				})
+			]),
+			custom_resources: vec![
+				CustomResourceConfig {
+					group: "argoproj.io".into(),
+					kind: "Rollout".into(),
+					pod_spec_path: "/spec/template/spec".into(),
+					pod_metadata_path: Some("/spec/template/metadata".into()),
+				},
+				CustomResourceConfig {
+					group: "tekton.dev".into(),
+					kind: "TaskRun".into(),
+					pod_spec_path: "/spec/podTemplate".into(),
+					pod_metadata_path: None,
+				},
+			],
+			..Default::default()
+		};
+
+		let mut state = TestAppState::new(config);
+		state.kubernetes.set_namespace_group("foo", "bar");
+
+		let request = Request::builder()
+			.uri("/mutate-workloads")
+			.header("Content-Type", "application/json")
+			.method("POST")
+			.body(body)
+			.unwrap();
+
+		server::build_app(state)
+			.oneshot(request)
+			.await
+			.unwrap()
+	}
+
+	fn template(node_selector: Option<Value>) -> Value {
+		let mut template = json!({
+			"metadata": {"labels": {"app": "test"}},
+			"spec": {"containers": [{"name": "test", "image": "alpine"}]},
+		});
+		if let Some(node_selector) = node_selector {
+			template["spec"]["nodeSelector"] = node_selector;
+		}
+		template
+	}
+
+	fn workload(api_version: &str, kind: &str, spec: Value) -> Value {
+		json!({
+			"apiVersion": api_version,
+			"kind": kind,
+			"metadata": {"name": "test", "namespace": "foo"},
+			"spec": spec,
+		})
+	}
+
+	#[tokio::test]
+	async fn when_deployment_is_created_should_patch_its_pod_template() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("apps", "v1", "Deployment")
+			.with_object(workload("apps/v1", "Deployment", json!({"replicas": 2, "template": template(None)})))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert_eq!(result.patches, vec![
+			patch::add("/spec/template/spec/nodeSelector".into(), json!({"label-0": "value-0"})),
+			patch::add("/spec/template/metadata/annotations".into(), json!({
+				"pod-director/group": "bar",
+				"pod-director/applied-node-selector": "label-0=value-0",
+			})),
+		]);
+	}
+
+	#[tokio::test]
+	async fn when_cron_job_is_created_should_patch_its_job_pod_template() {
+		let spec = json!({"schedule": "* * * * *", "jobTemplate": {"spec": {"template": template(Some(json!({})))}}});
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("batch", "v1", "CronJob")
+			.with_object(workload("batch/v1", "CronJob", spec))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.contains(
+			&patch::add("/spec/jobTemplate/spec/template/spec/nodeSelector/label-0".into(), json!("value-0"))
+		));
+	}
+
+	#[tokio::test]
+	async fn when_job_is_updated_without_changing_its_template_should_allow_it_unchanged() {
+		let job = |labels: Value| json!({
+			"apiVersion": "batch/v1",
+			"kind": "Job",
+			"metadata": {"name": "test", "namespace": "foo", "labels": labels},
+			"spec": {"template": template(None)},
+		});
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("batch", "v1", "Job")
+			.with_object(job(json!({"finished": "true"})))
+			.with_old_object(job(json!({})))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+	}
+
+	#[tokio::test]
+	async fn when_deployment_template_is_updated_should_patch_it() {
+		let mut old_template = template(None);
+		old_template["metadata"]["labels"]["app"] = json!("old");
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("apps", "v1", "Deployment")
+			.with_object(workload("apps/v1", "Deployment", json!({"template": template(None)})))
+			.with_old_object(workload("apps/v1", "Deployment", json!({"template": old_template})))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.contains(
+			&patch::add("/spec/template/spec/nodeSelector".into(), json!({"label-0": "value-0"}))
+		));
+	}
+
+	#[tokio::test]
+	async fn when_workload_template_conflicts_should_reject_it() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("apps", "v1", "StatefulSet")
+			.with_object(workload("apps/v1", "StatefulSet", json!({"template": template(Some(json!({"label-0": "other"})))})))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(!result.admission_response.allowed);
+		assert_eq!(
+			result.admission_response.result.message,
+			"The pod's nodeSelector label-0=other conflicts with pod-director's configuration label-0=value-0"
+		);
+	}
+
+	#[tokio::test]
+	async fn when_workload_kind_is_unsupported_should_allow_it_with_warning() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("apps", "v1", "DaemonSet")
+			.with_object(workload("apps/v1", "DaemonSet", json!({"template": template(None)})))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(result.admission_response.warnings, Some(vec![
+			"pod-director doesn't mutate DaemonSet objects, the MutatingWebhookConfiguration is probably misconfigured".to_owned(),
+		]));
+	}
+
+	#[tokio::test]
+	async fn when_custom_resource_is_configured_should_patch_its_pod_template() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("argoproj.io", "v1alpha1", "Rollout")
+			.with_object(workload("argoproj.io/v1alpha1", "Rollout", json!({"template": template(None)})))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert_eq!(result.patches, vec![
+			patch::add("/spec/template/spec/nodeSelector".into(), json!({"label-0": "value-0"})),
+			patch::add("/spec/template/metadata/annotations".into(), json!({
+				"pod-director/group": "bar",
+				"pod-director/applied-node-selector": "label-0=value-0",
+			})),
+		]);
+	}
+
+	#[tokio::test]
+	async fn when_custom_resource_template_lacks_metadata_should_add_it_before_annotating() {
+		let template = json!({"spec": {"containers": [{"name": "test", "image": "alpine"}]}});
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("argoproj.io", "v1alpha1", "Rollout")
+			.with_object(workload("argoproj.io/v1alpha1", "Rollout", json!({"template": template})))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert_eq!(result.patches, vec![
+			patch::add("/spec/template/spec/nodeSelector".into(), json!({"label-0": "value-0"})),
+			patch::add("/spec/template/metadata".into(), json!({})),
+			patch::add("/spec/template/metadata/annotations".into(), json!({
+				"pod-director/group": "bar",
+				"pod-director/applied-node-selector": "label-0=value-0",
+			})),
+		]);
+	}
+
+	#[tokio::test]
+	async fn when_custom_resource_has_no_pod_metadata_should_only_patch_its_spec() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("tekton.dev", "v1", "TaskRun")
+			.with_object(workload("tekton.dev/v1", "TaskRun", json!({"podTemplate": {"schedulerName": "default-scheduler"}})))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert_eq!(result.patches, vec![
+			patch::add("/spec/podTemplate/nodeSelector".into(), json!({"label-0": "value-0"})),
+		]);
+	}
+
+	#[tokio::test]
+	async fn when_object_has_no_pod_spec_should_allow_it_with_warning() {
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace("foo")
+			.with_kind("tekton.dev", "v1", "TaskRun")
+			.with_object(workload("tekton.dev/v1", "TaskRun", json!({"taskRef": {"name": "build"}})))
+			.build();
+
+		let result = ParsedResponse::from_response(mutate_workload_request(body).await).await;
+		assert_eq!(result.status, StatusCode::OK);
+		assert!(result.admission_response.allowed);
+		assert!(result.patches.is_empty());
+		assert_eq!(result.admission_response.warnings, Some(vec![
+			"processed object has no pod spec at /spec/podTemplate, it was not mutated".to_owned(),
+		]));
+	}
+}
@@ -1,5 +1,8 @@
-use std::sync::Arc;
+use std::process::ExitCode;
 
+use clap::Parser;
+
+mod cli;
 mod config;
 mod error;
 mod server;
@@ -10,15 +13,8 @@ mod handler;
 mod test_utils;
 
 #[tokio::main]
-async fn main() {
-	let config = Arc::new(config::Config::load().unwrap());
-	println!("Loaded config:");
-	println!("{config:?}");
+async fn main() -> ExitCode {
+	let cli = cli::Cli::parse();
 
-	match server::serve(config).await {
-		Ok(_) => {}
-		Err(e) => {
-			println!("ERROR: Failed serving server: {e}");
-		}
-	};
+	cli.command.unwrap_or(cli::Command::Serve).run().await
 }
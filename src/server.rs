@@ -8,32 +8,67 @@ use super::handler;
 
 pub use state::{AppState, StandardAppState};
 
-use crate::service::StandardKubernetesService;
+use crate::service::{Metrics, StandardKubernetesService};
 
 mod tls;
 mod shutdown;
 pub mod state;
 
+/// Routes called by the Kubernetes API server, served on the webhook listener, which must not expose anything else
 pub fn build_app<S: AppState>(state: S) -> Router {
 	Router::new()
-		.route("/health", get(handler::health::<S>))
 		.route("/mutate", post(handler::mutate::<S>))
+		.route("/mutate-workloads", post(handler::mutate_workload::<S>))
 		.with_state(state)
 }
 
-pub async fn serve(config: Arc<Config>) -> Result<()> {
-	let addr = config.server.socker_addr();
+/// Operational routes, such as health checks, metrics and explain, served over plain HTTP
+pub fn build_ops_app<S: AppState>(state: S) -> Router {
+	Router::new()
+		.route("/livez", get(handler::livez))
+		.route("/readyz", get(handler::readyz::<S>))
+		.route("/metrics", get(handler::metrics::<S>))
+		.route("/explain", post(handler::explain::<S>))
+		.with_state(state)
+}
 
+pub async fn serve(config: Arc<Config>) -> Result<()> {
 	let shutdown_handle = Handle::new();
 	tokio::spawn(shutdown::graceful_shutdown(shutdown_handle.clone()));
 
 	let kubernetes = StandardKubernetesService::new(&config.group_label).await?;
-	let app_state = StandardAppState::new(config.clone(), kubernetes);
-	let service = build_app(app_state).into_make_service();
+	let app_state = StandardAppState::new(config.clone(), kubernetes, Arc::new(Metrics::new()));
+
+	tokio::try_join!(
+		serve_ops(&config, app_state.clone(), shutdown_handle.clone()),
+		serve_webhook(&config, app_state, shutdown_handle),
+	)?;
+
+	Ok(())
+}
+
+async fn serve_ops(config: &Config, app_state: StandardAppState, shutdown_handle: Handle) -> Result<()> {
+	let addr = config.server.ops_socket_addr();
+	let service = build_ops_app(app_state).into_make_service();
+
+	println!("Operational server starting, listening on {addr}");
+
+	axum_server::bind(addr)
+		.handle(shutdown_handle)
+		.serve(service)
+		.await?;
+
+	Ok(())
+}
+
+async fn serve_webhook(config: &Config, app_state: StandardAppState, shutdown_handle: Handle) -> Result<()> {
+	let addr = config.server.socker_addr();
+	let service = build_app(app_state.clone()).into_make_service();
 
 	println!("Server starting, listening on {addr}");
 
 	if config.server.insecure {
+		app_state.set_tls_loaded(true);
 		axum_server::bind(addr)
 			.handle(shutdown_handle)
 			.serve(service)
@@ -41,6 +76,7 @@ pub async fn serve(config: Arc<Config>) -> Result<()> {
 	}
 	else {
 		let tls_config = config.server.tls_config().await?;
+		app_state.set_tls_loaded(true);
 		let hot_reload = tokio::spawn(tls::hot_reload_tls(
 			tls_config.clone(),
 			config.server.cert.clone(),
@@ -1,23 +1,32 @@
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use crate::config::Config;
-use crate::service::{KubernetesService, StandardKubernetesService};
+use crate::service::{KubernetesService, Metrics, StandardKubernetesService};
 
 pub trait AppState: Clone + Send + Sync + 'static {
 	type K: KubernetesService;
 
 	fn config(&self) -> &Config;
 	fn kubernetes(&self) -> &Self::K;
+	fn metrics(&self) -> &Metrics;
+	fn tls_loaded(&self) -> bool;
 }
 
 #[derive(Clone)]
 pub struct StandardAppState {
 	config: Arc<Config>,
 	kubernetes: StandardKubernetesService,
+	metrics: Arc<Metrics>,
+	tls_loaded: Arc<AtomicBool>,
 }
 
 impl StandardAppState {
-	pub fn new(config: Arc<Config>, kubernetes: StandardKubernetesService) -> Self {
-		Self { config, kubernetes }
+	pub fn new(config: Arc<Config>, kubernetes: StandardKubernetesService, metrics: Arc<Metrics>) -> Self {
+		Self { config, kubernetes, metrics, tls_loaded: Arc::new(AtomicBool::new(false)) }
+	}
+
+	pub fn set_tls_loaded(&self, loaded: bool) {
+		self.tls_loaded.store(loaded, Ordering::Relaxed);
 	}
 }
 
@@ -31,6 +40,14 @@ impl AppState for StandardAppState {
 	fn kubernetes(&self) -> &Self::K {
 		&self.kubernetes
 	}
+
+	fn metrics(&self) -> &Metrics {
+		&self.metrics
+	}
+
+	fn tls_loaded(&self) -> bool {
+		self.tls_loaded.load(Ordering::Relaxed)
+	}
 }
 
 #[cfg(test)]
@@ -38,19 +55,24 @@ pub mod tests {
 	use std::sync::Arc;
 	use crate::config::Config;
 	use crate::server::AppState;
+	use crate::service::Metrics;
 	use crate::service::tests::MockKubernetesService;
 
 	#[derive(Clone)]
 	pub struct TestAppState {
 		config: Arc<Config>,
 		pub kubernetes: MockKubernetesService,
+		pub metrics: Arc<Metrics>,
+		pub tls_loaded: bool,
 	}
 
 	impl TestAppState {
 		pub fn new(config: Config) -> Self {
 			Self {
+				kubernetes: MockKubernetesService::new(&config.group_label),
 				config: Arc::new(config),
-				kubernetes: MockKubernetesService::new()
+				metrics: Arc::new(Metrics::new()),
+				tls_loaded: true,
 			}
 		}
 	}
@@ -65,5 +87,13 @@ pub mod tests {
 		fn kubernetes(&self) -> &Self::K {
 			&self.kubernetes
 		}
+
+		fn metrics(&self) -> &Metrics {
+			&self.metrics
+		}
+
+		fn tls_loaded(&self) -> bool {
+			self.tls_loaded
+		}
 	}
 }
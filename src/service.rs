@@ -1,6 +1,8 @@
 mod kubernetes;
+mod metrics;
 
 pub use kubernetes::{KubernetesService, StandardKubernetesService};
+pub use metrics::Metrics;
 
 #[cfg(test)]
 pub use kubernetes::tests;
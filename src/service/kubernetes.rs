@@ -1,5 +1,7 @@
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 use axum::async_trait;
 use k8s_openapi::api::core::v1::Namespace;
 use kube::{Api, Client, ResourceExt};
@@ -12,60 +14,90 @@ use kube::runtime::reflector::store::Writer;
 pub trait KubernetesService: Send + Sync + Clone {
     async fn namespace_group<S: AsRef<str> + Send + Sync>(&self, namespace: S) -> Option<String>;
 
-    async fn healthy(&self) -> bool;
+    /// A snapshot of the cached namespace's metadata, None if the namespace is not cached
+    async fn namespace<S: AsRef<str> + Send + Sync>(&self, namespace: S) -> Option<NamespaceMetadata>;
+
+    async fn watcher_status(&self) -> WatcherStatus;
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NamespaceMetadata {
+    pub labels: BTreeMap<String, String>,
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WatcherStatus {
+    /// Whether the namespace cache has been fully populated at least once
+    pub synced: bool,
+    /// When the watcher started failing, if it's currently failing
+    pub failing_since: Option<Instant>,
 }
 
 #[derive(Clone)]
 pub struct StandardKubernetesService {
     store: Store<Namespace>,
     group_label: String,
-    healthy: Arc<AtomicBool>,
+    synced: Arc<AtomicBool>,
+    failing_since: Arc<Mutex<Option<Instant>>>,
 }
 
 impl StandardKubernetesService {
     pub async fn new<S: AsRef<str>>(group_label: S) -> anyhow::Result<Self> {
         let api: Api<Namespace> = Api::all(Client::try_default().await?);
-        // TODO: Map errors to healthcheck
         let watcher = kube::runtime::watcher(api, Default::default());
 
         let (reader, writer) = kube::runtime::reflector::store();
 
-        let healthy = Arc::new(AtomicBool::new(true));
-
-        Self::watch_namespaces(watcher, writer, &healthy);
+        let synced = Arc::new(AtomicBool::new(false));
+        let failing_since = Arc::new(Mutex::new(None));
 
-        reader.wait_until_ready().await?;
+        Self::watch_namespaces(watcher, writer, &failing_since);
+        Self::watch_sync(reader.clone(), &synced);
 
         Ok(StandardKubernetesService {
             store: reader,
             group_label: group_label.as_ref().to_string(),
-            healthy,
+            synced,
+            failing_since,
         })
     }
 
     fn watch_namespaces(
         watcher: impl Stream<Item=watcher::Result<watcher::Event<Namespace>>> + Send + Sized + 'static,
         writer: Writer<Namespace>,
-        healthy: &Arc<AtomicBool>,
+        failing_since: &Arc<Mutex<Option<Instant>>>,
     ) {
-        let healthy_clone = Arc::clone(&healthy);
+        let failing_since_clone = Arc::clone(failing_since);
         let stream = reflector(writer, watcher)
             .default_backoff()
             .touched_objects()
             .for_each(move |r| {
-                let reflector_healthy = Arc::clone(&healthy_clone);
-                future::ready(match r {
+                let mut reflector_failing_since = failing_since_clone.lock().unwrap();
+                match r {
                     Ok(_) => {
-                        reflector_healthy.store(true, Ordering::Relaxed);
+                        *reflector_failing_since = None;
                     }
                     Err(e) => {
-                        reflector_healthy.store(false, Ordering::Relaxed);
+                        reflector_failing_since.get_or_insert_with(Instant::now);
                         println!("watcher error: {e}")
                     }
-                })
+                };
+                future::ready(())
             });
         tokio::spawn(stream);
     }
+
+    /// The store is only populated after the initial listing, until then the pod must not receive traffic
+    fn watch_sync(reader: Store<Namespace>, synced: &Arc<AtomicBool>) {
+        let synced_clone = Arc::clone(synced);
+        tokio::spawn(async move {
+            if reader.wait_until_ready().await.is_ok() {
+                synced_clone.store(true, Ordering::Relaxed);
+                println!("Namespace cache synced");
+            }
+        });
+    }
 }
 
 #[async_trait]
@@ -77,52 +109,87 @@ impl KubernetesService for StandardKubernetesService {
             Some(n) => n,
         };
 
-        let result = match namespace.labels().get(&self.group_label) {
-            None => None,
-            Some(s) => Some(s.to_string())
-        };
+        namespace.labels().get(&self.group_label).map(String::to_string)
+    }
+
+    async fn namespace<S: AsRef<str> + Send + Sync>(&self, namespace_name: S) -> Option<NamespaceMetadata> {
+        let namespace_ref = &ObjectRef::<Namespace>::new(namespace_name.as_ref());
 
-        result
+        self.store.get(namespace_ref).map(|n| NamespaceMetadata {
+            labels: n.labels().clone(),
+            annotations: n.annotations().clone(),
+        })
     }
 
-    async fn healthy(&self) -> bool { self.healthy.load(Ordering::Relaxed) }
+    async fn watcher_status(&self) -> WatcherStatus {
+        WatcherStatus {
+            synced: self.synced.load(Ordering::Relaxed),
+            failing_since: *self.failing_since.lock().unwrap(),
+        }
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
     use std::collections::BTreeMap;
+    use std::time::{Duration, Instant};
     use axum::async_trait;
-    use crate::service::kubernetes::KubernetesService;
+    use crate::service::kubernetes::{KubernetesService, NamespaceMetadata, WatcherStatus};
 
     #[derive(Clone)]
     pub struct MockKubernetesService {
-        namespace_group_map: BTreeMap<String, String>,
-        is_error: bool,
+        group_label: String,
+        namespaces: BTreeMap<String, NamespaceMetadata>,
+        watcher_status: WatcherStatus,
     }
 
     impl MockKubernetesService {
-        pub fn new() -> Self {
+        pub fn new<S: AsRef<str>>(group_label: S) -> Self {
             MockKubernetesService {
-                namespace_group_map: BTreeMap::new(),
-                is_error: false,
+                group_label: group_label.as_ref().into(),
+                namespaces: BTreeMap::new(),
+                watcher_status: WatcherStatus { synced: true, failing_since: None },
             }
         }
 
         pub fn set_namespace_group<S: AsRef<str>, R: AsRef<str>>(&mut self, namespace: S, group: R) {
-            self.namespace_group_map.insert(namespace.as_ref().into(), group.as_ref().into());
+            let group_label = self.group_label.clone();
+            self.set_namespace_label(namespace, group_label, group);
         }
 
-        pub fn set_error(&mut self, is_erroring: bool) {
-            self.is_error = is_erroring;
+        pub fn set_namespace_label<S: AsRef<str>, R: AsRef<str>, T: AsRef<str>>(&mut self, namespace: S, key: R, value: T) {
+            self.namespaces.entry(namespace.as_ref().into())
+                .or_default()
+                .labels
+                .insert(key.as_ref().into(), value.as_ref().into());
+        }
+
+        pub fn set_namespace_annotation<S: AsRef<str>, R: AsRef<str>, T: AsRef<str>>(&mut self, namespace: S, key: R, value: T) {
+            self.namespaces.entry(namespace.as_ref().into())
+                .or_default()
+                .annotations
+                .insert(key.as_ref().into(), value.as_ref().into());
+        }
+
+        pub fn set_synced(&mut self, synced: bool) {
+            self.watcher_status.synced = synced;
+        }
+
+        pub fn set_failing_for(&mut self, duration: Duration) {
+            self.watcher_status.failing_since = Some(Instant::now() - duration);
         }
     }
 
     #[async_trait]
     impl KubernetesService for MockKubernetesService {
         async fn namespace_group<S: AsRef<str> + Send + Sync>(&self, namespace: S) -> Option<String> {
-            self.namespace_group_map.get(namespace.as_ref()).map(String::to_owned)
+            self.namespaces.get(namespace.as_ref())?.labels.get(&self.group_label).map(String::to_owned)
+        }
+
+        async fn namespace<S: AsRef<str> + Send + Sync>(&self, namespace: S) -> Option<NamespaceMetadata> {
+            self.namespaces.get(namespace.as_ref()).cloned()
         }
 
-        async fn healthy(&self) -> bool { !self.is_error }
+        async fn watcher_status(&self) -> WatcherStatus { self.watcher_status.clone() }
     }
 }
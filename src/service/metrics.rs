@@ -0,0 +1,84 @@
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::encoding::text::encode;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::registry::Registry;
+
+use crate::config::Mode;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct AdmissionLabels {
+	group: String,
+	mode: &'static str,
+	result: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct PatchLabels {
+	group: String,
+	mode: &'static str,
+}
+
+pub struct Metrics {
+	registry: Registry,
+	admissions: Family<AdmissionLabels, Counter>,
+	patches: Family<PatchLabels, Counter>,
+}
+
+impl Metrics {
+	pub fn new() -> Self {
+		let mut registry = Registry::with_prefix("pod_director");
+		let admissions = Family::<AdmissionLabels, Counter>::default();
+		let patches = Family::<PatchLabels, Counter>::default();
+
+		registry.register(
+			"admissions",
+			"Pods admitted for a pod-director group, by mode and result",
+			admissions.clone(),
+		);
+		registry.register(
+			"patches",
+			"Patch operations calculated for pods, in dry run mode these were not applied",
+			patches.clone(),
+		);
+
+		Self { registry, admissions, patches }
+	}
+
+	pub fn record_admission(&self, group: &str, mode: Mode, allowed: bool, patch_count: usize) {
+		self.admissions.get_or_create(&AdmissionLabels {
+			group: group.to_string(),
+			mode: mode.as_str(),
+			result: if allowed { "allowed" } else { "denied" },
+		}).inc();
+
+		self.patches.get_or_create(&PatchLabels {
+			group: group.to_string(),
+			mode: mode.as_str(),
+		}).inc_by(patch_count as u64);
+	}
+
+	/// Renders all metrics in the OpenMetrics text format
+	pub fn encode(&self) -> String {
+		let mut buffer = String::new();
+		encode(&mut buffer, &self.registry).expect("Writing to a String can't fail");
+		buffer
+	}
+
+	#[cfg(test)]
+	pub fn admissions(&self, group: &str, mode: Mode, allowed: bool) -> u64 {
+		self.admissions.get_or_create(&AdmissionLabels {
+			group: group.to_string(),
+			mode: mode.as_str(),
+			result: if allowed { "allowed" } else { "denied" },
+		}).get()
+	}
+
+	#[cfg(test)]
+	pub fn patches(&self, group: &str, mode: Mode) -> u64 {
+		self.patches.get_or_create(&PatchLabels {
+			group: group.to_string(),
+			mode: mode.as_str(),
+		}).get()
+	}
+}
@@ -1,5 +1,7 @@
+mod golden;
 mod pod;
 mod response;
 
+pub use golden::AdmissionCase;
 pub use pod::PodCreateRequestBuilder;
 pub use response::ParsedResponse;
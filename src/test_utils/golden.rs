@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use axum::http::Request;
+use json_patch::PatchOperation;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tower::ServiceExt;
+
+use crate::config::Config;
+use crate::server;
+use crate::server::state::tests::TestAppState;
+use crate::test_utils::{ParsedResponse, PodCreateRequestBuilder};
+
+/// Directory holding the admission cases, one directory per case
+pub static ADMISSION_CASES_DIR: &str = "tests/fixtures/admission";
+
+/// A data driven admission scenario, loaded from a directory containing:
+/// - `config.yaml`: pod-director's configuration
+/// - `namespace.yaml`: the pod's namespace name, labels and annotations
+/// - `pod.yaml`: the pod being admitted
+/// - `expected.yaml`: whether the pod is allowed, along with the expected patches, warnings or denial message
+pub struct AdmissionCase {
+	pub name: String,
+	config: Config,
+	namespace: Namespace,
+	pod: Value,
+	expected: Expected,
+}
+
+#[derive(Deserialize)]
+struct Namespace {
+	name: String,
+	#[serde(default)]
+	labels: BTreeMap<String, String>,
+	#[serde(default)]
+	annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Expected {
+	allowed: bool,
+	#[serde(default)]
+	patches: Vec<PatchOperation>,
+	warnings: Option<Vec<String>>,
+	message: Option<String>,
+}
+
+impl AdmissionCase {
+	pub fn load(dir: &Path) -> Self {
+		let name = dir.file_name().unwrap().to_string_lossy().to_string();
+		let config = Config::load_file(dir.join("config.yaml"))
+			.unwrap_or_else(|e| panic!("case {name}: failed loading config.yaml: {e}"));
+
+		Self {
+			namespace: read_yaml(&name, &dir.join("namespace.yaml")),
+			pod: read_yaml(&name, &dir.join("pod.yaml")),
+			expected: read_yaml(&name, &dir.join("expected.yaml")),
+			config,
+			name,
+		}
+	}
+
+	/// Loads every case in the admission cases directory, sorted by name
+	pub fn load_all() -> Vec<Self> {
+		let root = Path::new(env!("CARGO_MANIFEST_DIR")).join(ADMISSION_CASES_DIR);
+		let mut dirs: Vec<PathBuf> = fs::read_dir(&root)
+			.unwrap_or_else(|e| panic!("failed reading {}: {e}", root.display()))
+			.map(|entry| entry.unwrap().path())
+			.filter(|path| path.is_dir())
+			.collect();
+		dirs.sort();
+
+		dirs.iter().map(|dir| Self::load(dir)).collect()
+	}
+
+	/// Runs the case through the application, returning a description of every mismatch against the expectations
+	pub async fn run(self) -> Result<(), String> {
+		let mut state = TestAppState::new(self.config);
+		for (key, value) in &self.namespace.labels {
+			state.kubernetes.set_namespace_label(&self.namespace.name, key, value);
+		}
+		for (key, value) in &self.namespace.annotations {
+			state.kubernetes.set_namespace_annotation(&self.namespace.name, key, value);
+		}
+
+		let body = PodCreateRequestBuilder::new()
+			.with_namespace(&self.namespace.name)
+			.with_object(self.pod)
+			.build();
+
+		let request = Request::builder()
+			.uri("/mutate")
+			.header("Content-Type", "application/json")
+			.method("POST")
+			.body(body)
+			.unwrap();
+
+		let response = server::build_app(state)
+			.oneshot(request)
+			.await
+			.unwrap();
+		let result = ParsedResponse::from_response(response).await;
+		let message = (!result.admission_response.allowed).then(|| result.admission_response.result.message.clone());
+
+		let actual = Expected {
+			allowed: result.admission_response.allowed,
+			patches: result.patches,
+			warnings: result.admission_response.warnings,
+			message,
+		};
+
+		match actual == self.expected {
+			true => Ok(()),
+			false => Err(format!(
+				"case {}:\n  expected: {:?}\n  actual:   {:?}",
+				self.name, self.expected, actual,
+			)),
+		}
+	}
+}
+
+fn read_yaml<T: DeserializeOwned>(case: &str, path: &Path) -> T {
+	let content = fs::read_to_string(path)
+		.unwrap_or_else(|e| panic!("case {case}: failed reading {}: {e}", path.display()));
+	serde_yaml::from_str(&content)
+		.unwrap_or_else(|e| panic!("case {case}: failed parsing {}: {e}", path.display()))
+}
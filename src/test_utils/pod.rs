@@ -1,17 +1,53 @@
 use std::collections::BTreeMap;
 use axum::body::Body;
-use k8s_openapi::api::core::v1::Toleration;
-use serde_json::json;
+use k8s_openapi::api::core::v1::{Toleration, TopologySpreadConstraint};
+use serde_json::{json, Value};
 
 pub struct PodCreateRequestBuilder {
 	namespace: Option<String>,
+	labels: BTreeMap<String, String>,
+	annotations: Option<BTreeMap<String, String>>,
+	owner_references: Option<Vec<Value>>,
 	node_selector: Option<BTreeMap<String, String>>,
-	tolerations: Option<Vec<Toleration>>
+	tolerations: Option<Vec<Toleration>>,
+	topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+	priority_class_name: Option<String>,
+	scheduler_name: String,
+	runtime_class_name: Option<String>,
+	overhead: Option<Value>,
+	service_account_name: String,
+	resources: Value,
+	env: Option<Vec<Value>>,
+	init_containers: Option<Vec<Value>>,
+	dry_run: bool,
+	object: Option<Value>,
+	old_object: Option<Value>,
+	kind: Option<(String, String, String)>,
 }
 
 impl PodCreateRequestBuilder {
 	pub fn new() -> Self {
-		Self { namespace: None, node_selector: None, tolerations: None }
+		Self {
+			namespace: None,
+			labels: BTreeMap::from([("run".into(), "test".into())]),
+			annotations: None,
+			owner_references: None,
+			node_selector: None,
+			tolerations: None,
+			topology_spread_constraints: None,
+			priority_class_name: None,
+			scheduler_name: "default-scheduler".into(),
+			runtime_class_name: None,
+			overhead: None,
+			service_account_name: "default".into(),
+			resources: json!({}),
+			env: None,
+			init_containers: None,
+			dry_run: false,
+			object: None,
+			old_object: None,
+			kind: None,
+		}
 	}
 
 	pub fn with_namespace<S: AsRef<str>>(mut self, namespace: S) -> Self {
@@ -19,6 +55,31 @@ impl PodCreateRequestBuilder {
 		self
 	}
 
+	pub fn with_label<S: AsRef<str>, R: AsRef<str>>(mut self, key: S, value: R) -> Self {
+		self.labels.insert(key.as_ref().into(), value.as_ref().into());
+		self
+	}
+
+	pub fn with_annotation<S: AsRef<str>, R: AsRef<str>>(mut self, key: S, value: R) -> Self {
+		self.annotations.get_or_insert_with(BTreeMap::new)
+			.insert(key.as_ref().into(), value.as_ref().into());
+		self
+	}
+
+	/// Adds an owner reference marked as the pod's controller
+	pub fn with_owner_reference<S: AsRef<str>, R: AsRef<str>, T: AsRef<str>>(mut self, api_version: S, kind: R, name: T) -> Self {
+		self.owner_references.get_or_insert_with(Vec::new)
+			.push(json!({
+				"apiVersion": api_version.as_ref(),
+				"kind": kind.as_ref(),
+				"name": name.as_ref(),
+				"uid": "7d7bf6a1-5e4f-4d3c-9c1e-3f4c2b1a0e9d",
+				"controller": true,
+				"blockOwnerDeletion": true,
+			}));
+		self
+	}
+
 	pub fn with_node_selector<S: AsRef<str>, R: AsRef<str>>(mut self, label: S, value: R) -> Self {
 		self.node_selector.get_or_insert_with(BTreeMap::new)
 			.insert(label.as_ref().into(), value.as_ref().into());
@@ -31,8 +92,82 @@ impl PodCreateRequestBuilder {
 		self
 	}
 
+	pub fn with_topology_spread_constraint(mut self, constraint: TopologySpreadConstraint) -> Self {
+		self.topology_spread_constraints.get_or_insert_with(Vec::new)
+			.push(constraint);
+		self
+	}
+
+	pub fn with_priority_class_name<S: AsRef<str>>(mut self, priority_class_name: S) -> Self {
+		self.priority_class_name = Some(priority_class_name.as_ref().to_string());
+		self
+	}
+
+	pub fn with_scheduler_name<S: AsRef<str>>(mut self, scheduler_name: S) -> Self {
+		self.scheduler_name = scheduler_name.as_ref().to_string();
+		self
+	}
+
+	pub fn with_runtime_class_name<S: AsRef<str>>(mut self, runtime_class_name: S) -> Self {
+		self.runtime_class_name = Some(runtime_class_name.as_ref().to_string());
+		self
+	}
+
+	/// Sets the pod's overhead, as resolved from its runtime class by the API server
+	pub fn with_overhead(mut self, overhead: Value) -> Self {
+		self.overhead = Some(overhead);
+		self
+	}
+
+	pub fn with_service_account_name<S: AsRef<str>>(mut self, service_account_name: S) -> Self {
+		self.service_account_name = service_account_name.as_ref().to_string();
+		self
+	}
+
+	/// Sets the resources of the pod's only container
+	pub fn with_resources(mut self, resources: Value) -> Self {
+		self.resources = resources;
+		self
+	}
+
+	/// Adds an environment variable to the pod's only container
+	pub fn with_env<S: AsRef<str>, R: AsRef<str>>(mut self, name: S, value: R) -> Self {
+		self.env.get_or_insert_with(Vec::new)
+			.push(json!({"name": name.as_ref(), "value": value.as_ref()}));
+		self
+	}
+
+	pub fn with_init_container(mut self, init_container: Value) -> Self {
+		self.init_containers.get_or_insert_with(Vec::new)
+			.push(init_container);
+		self
+	}
+
+	pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+		self.dry_run = dry_run;
+		self
+	}
+
+	/// Replaces the whole pod in the request, ignoring the pod specific options of this builder
+	pub fn with_object(mut self, object: Value) -> Self {
+		self.object = Some(object);
+		self
+	}
+
+	/// Turns the request into an UPDATE of the given object, to be used along with `with_object`
+	pub fn with_old_object(mut self, old_object: Value) -> Self {
+		self.old_object = Some(old_object);
+		self
+	}
+
+	/// Sets the kind of the request's object, to be used along with `with_object` for anything but pods
+	pub fn with_kind<S: AsRef<str>, R: AsRef<str>, T: AsRef<str>>(mut self, group: S, version: R, kind: T) -> Self {
+		self.kind = Some((group.as_ref().to_string(), version.as_ref().to_string(), kind.as_ref().to_string()));
+		self
+	}
+
 	pub fn build(self) -> Body {
-		let data = json!({
+		let mut data = json!({
 		  "apiVersion": "admission.k8s.io/v1",
 		  "kind": "AdmissionReview",
 		  "request": {
@@ -75,20 +210,21 @@ impl PodCreateRequestBuilder {
 		      "apiVersion": "v1",
 		      "kind": "Pod",
 		      "metadata": {
-		        "labels": {
-		          "run": "test"
-		        },
+		        "annotations": self.annotations,
+		        "labels": self.labels,
 		        "managedFields": [],
 		        "name": "test",
-		        "namespace": "test"
+		        "namespace": "test",
+		        "ownerReferences": self.owner_references
 		      },
 		      "spec": {
 		      "containers": [{
 		        "args": ["sh"],
+		        "env": self.env,
 		        "image": "alpine",
 		        "imagePullPolicy": "Always",
 		        "name": "test",
-		        "resources": {},
+		        "resources": self.resources,
 		        "stdin": true,
 		        "stdinOnce": true,
 		        "terminationMessagePath": "/dev/termination-log",
@@ -100,24 +236,29 @@ impl PodCreateRequestBuilder {
 		          "readOnly": true
 		        }]
 		      }],
+		      "initContainers": self.init_containers,
 		      "nodeSelector": self.node_selector,
+		      "overhead": self.overhead,
 		      "dnsPolicy": "ClusterFirst",
 		      "enableServiceLinks": true,
 		      "preemptionPolicy": "PreemptLowerPriority",
 		      "priority": 0,
+		      "priorityClassName": self.priority_class_name,
 		      "restartPolicy": "Always",
-		      "schedulerName": "default-scheduler",
+		      "runtimeClassName": self.runtime_class_name,
+		      "schedulerName": self.scheduler_name,
 		      "securityContext": {},
-		      "serviceAccount": "default",
-		      "serviceAccountName": "default",
+		      "serviceAccount": self.service_account_name,
+		      "serviceAccountName": self.service_account_name,
 		      "terminationGracePeriodSeconds": 30,
 		      "tolerations": self.tolerations,
+		      "topologySpreadConstraints": self.topology_spread_constraints,
 		      "volumes": []
 		      },
 		      "status": {}
 		    },
 		    "oldObject": null,
-		    "dryRun": false,
+		    "dryRun": self.dry_run,
 		    "options": {
 		      "apiVersion": "meta.k8s.io/v1",
 		      "fieldManager": "kubectl-run",
@@ -126,6 +267,23 @@ impl PodCreateRequestBuilder {
 		  }
 		});
 
+		if let Some(object) = self.object {
+			data["request"]["object"] = object;
+		}
+
+		if let Some(old_object) = self.old_object {
+			data["request"]["operation"] = json!("UPDATE");
+			data["request"]["oldObject"] = old_object;
+		}
+
+		if let Some((group, version, kind)) = self.kind {
+			let resource = format!("{}s", kind.to_lowercase());
+			data["request"]["kind"] = json!({"group": group, "version": version, "kind": kind});
+			data["request"]["requestKind"] = data["request"]["kind"].clone();
+			data["request"]["resource"] = json!({"group": group, "version": version, "resource": resource});
+			data["request"]["requestResource"] = data["request"]["resource"].clone();
+		}
+
 		Body::from(serde_json::to_vec(&data).unwrap())
 	}
 }
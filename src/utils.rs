@@ -1 +1,4 @@
+pub mod image;
+pub mod label;
 pub mod patch;
+pub mod quantity;
@@ -0,0 +1,76 @@
+static DEFAULT_REGISTRY: &str = "docker.io";
+
+/// Expands an image reference to include its registry and repository namespace, as resolved by container runtimes
+///
+/// For example, `alpine:3` becomes `docker.io/library/alpine:3` and `grafana/grafana` becomes `docker.io/grafana/grafana`.
+pub fn normalize(image: &str) -> String {
+	match image.split_once('/') {
+		None => format!("{DEFAULT_REGISTRY}/library/{image}"),
+		Some((registry, _)) if registry.contains(['.', ':']) || registry == "localhost" => image.to_string(),
+		Some(_) => format!("{DEFAULT_REGISTRY}/{image}"),
+	}
+}
+
+/// Checks if the image, either as written or normalized, matches the pattern, where `*` matches any characters
+pub fn matches(pattern: &str, image: &str) -> bool {
+	glob(pattern, image) || glob(pattern, &normalize(image))
+}
+
+fn glob(pattern: &str, text: &str) -> bool {
+	let mut parts = pattern.split('*');
+	let first = parts.next().unwrap_or_default();
+
+	let Some(mut rest) = text.strip_prefix(first) else {
+		return false;
+	};
+
+	let mut parts: Vec<&str> = parts.collect();
+	let Some(last) = parts.pop() else {
+		return rest.is_empty();
+	};
+
+	for part in parts {
+		match rest.find(part) {
+			Some(i) => rest = &rest[i + part.len()..],
+			None => return false,
+		}
+	}
+
+	rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{matches, normalize};
+
+	#[test]
+	fn given_images_should_normalize_them() {
+		assert_eq!(normalize("alpine"), "docker.io/library/alpine");
+		assert_eq!(normalize("alpine:3.19"), "docker.io/library/alpine:3.19");
+		assert_eq!(normalize("grafana/grafana:10"), "docker.io/grafana/grafana:10");
+		assert_eq!(normalize("ghcr.io/org/app@sha256:abc"), "ghcr.io/org/app@sha256:abc");
+		assert_eq!(normalize("localhost/app"), "localhost/app");
+		assert_eq!(normalize("registry:5000/app"), "registry:5000/app");
+	}
+
+	#[test]
+	fn given_patterns_should_match_images() {
+		assert!(matches("alpine", "alpine"));
+		assert!(matches("alpine:*", "alpine:3.19"));
+		assert!(matches("docker.io/*", "alpine"));
+		assert!(matches("*.example.com/*", "registry.example.com/team/app:1"));
+		assert!(matches("*-arm64", "registry.example.com/app:1.0-arm64"));
+		assert!(matches("*", "anything"));
+		assert!(matches("a*b*c", "abc"));
+	}
+
+	#[test]
+	fn given_patterns_should_not_match_other_images() {
+		assert!(!matches("alpine", "alpine:3.19"));
+		assert!(!matches("docker.io/*", "ghcr.io/org/app"));
+		assert!(!matches("*.example.com/*", "example.com/app"));
+		assert!(!matches("*-arm64", "app:1.0-amd64"));
+		assert!(!matches("a*b*c", "acb"));
+		assert!(!matches("ab*ba", "aba"));
+	}
+}
@@ -0,0 +1,70 @@
+/// Checks if the string is a valid label key, with an optional DNS subdomain prefix, such as `kubernetes.io/os`
+pub fn is_valid_key(key: &str) -> bool {
+	let (prefix, name) = match key.split_once('/') {
+		Some((prefix, name)) => (Some(prefix), name),
+		None => (None, key),
+	};
+
+	if let Some(prefix) = prefix {
+		if prefix.is_empty() || prefix.len() > 253 || !prefix.split('.').all(is_dns_label) {
+			return false;
+		}
+	}
+
+	!name.is_empty() && is_valid_value(name)
+}
+
+/// Checks if the string is a valid label value, empty values are allowed
+pub fn is_valid_value(value: &str) -> bool {
+	if value.is_empty() {
+		return true;
+	}
+
+	value.len() <= 63
+		&& value.starts_with(|c: char| c.is_ascii_alphanumeric())
+		&& value.ends_with(|c: char| c.is_ascii_alphanumeric())
+		&& value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn is_dns_label(label: &str) -> bool {
+	!label.is_empty()
+		&& label.len() <= 63
+		&& label.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+		&& label.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+		&& label.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_valid_key, is_valid_value};
+
+	#[test]
+	fn given_valid_keys_should_accept_them() {
+		assert!(is_valid_key("role"));
+		assert!(is_valid_key("pod-director/group"));
+		assert!(is_valid_key("node.kubernetes.io/instance-type"));
+		assert!(is_valid_key("Some_Label.name"));
+	}
+
+	#[test]
+	fn given_invalid_keys_should_reject_them() {
+		assert!(!is_valid_key(""));
+		assert!(!is_valid_key("/name"));
+		assert!(!is_valid_key("prefix/"));
+		assert!(!is_valid_key("Upper.Case/name"));
+		assert!(!is_valid_key("a/b/c"));
+		assert!(!is_valid_key("not-valid-@-label"));
+		assert!(!is_valid_key(&"a".repeat(64)));
+	}
+
+	#[test]
+	fn given_label_values_should_validate_them() {
+		assert!(is_valid_value(""));
+		assert!(is_valid_value("some-value"));
+		assert!(is_valid_value("v1.2_3"));
+		assert!(!is_valid_value("-leading"));
+		assert!(!is_valid_value("trailing."));
+		assert!(!is_valid_value("SomeGroup@Name"));
+		assert!(!is_valid_value(&"a".repeat(64)));
+	}
+}
@@ -1,10 +1,14 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
 
 use json_patch::PatchOperation;
-use k8s_openapi::api::core::v1::{PodSpec, Toleration};
+use k8s_openapi::api::core::v1::{Container, EnvVar, Pod, PodSpec, Toleration, TopologySpreadConstraint, Volume, VolumeMount};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use crate::config::Conflict;
+use crate::config::{Conflict, InjectionConfig, ResourcesConfig};
+use crate::utils::quantity;
 
 pub fn add(path: String, value: Value) -> PatchOperation {
 	PatchOperation::Add(json_patch::AddOperation {
@@ -20,70 +24,900 @@ pub fn replace(path: String, value: Value) -> PatchOperation {
 	})
 }
 
-pub enum PatchResult<'a> {
+pub fn remove(path: String) -> PatchOperation {
+	PatchOperation::Remove(json_patch::RemoveOperation {
+		path,
+	})
+}
+
+/// Builds a JSON pointer from reference tokens, escaping `~` and `/` in each of them as per RFC 6901
+///
+/// Label and annotation keys commonly contain `/`, such as `kubernetes.io/os`, which would otherwise point at a nested field.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pointer(String);
+
+impl Pointer {
+	pub fn new(tokens: &[&str]) -> Self {
+		tokens.iter().fold(Self::default(), |pointer, token| pointer.push(token))
+	}
+
+	pub fn push(mut self, token: &str) -> Self {
+		self.0.push('/');
+		self.0.push_str(&token.replace('~', "~0").replace('/', "~1"));
+		self
+	}
+
+	/// Reads an already escaped JSON pointer, such as one from configuration, `None` if it is not a valid pointer
+	pub fn from_escaped(pointer: &str) -> Option<Self> {
+		Self::parse(pointer).map(|tokens| tokens.iter().fold(Self::default(), |pointer, token| pointer.push(token)))
+	}
+
+	pub fn join(&self, tokens: &[&str]) -> Self {
+		tokens.iter().fold(self.clone(), |pointer, token| pointer.push(token))
+	}
+
+	/// Splits a JSON pointer into its unescaped reference tokens, or `None` if it is not a valid pointer
+	pub fn parse(pointer: &str) -> Option<Vec<String>> {
+		if pointer.is_empty() {
+			return Some(Vec::new());
+		}
+
+		pointer.strip_prefix('/')?
+			.split('/')
+			.map(unescape)
+			.collect()
+	}
+}
+
+fn unescape(token: &str) -> Option<String> {
+	let mut unescaped = String::with_capacity(token.len());
+	let mut chars = token.chars();
+
+	while let Some(c) = chars.next() {
+		match c {
+			'~' => match chars.next()? {
+				'0' => unescaped.push('~'),
+				'1' => unescaped.push('/'),
+				_ => return None,
+			},
+			c => unescaped.push(c),
+		}
+	}
+
+	Some(unescaped)
+}
+
+impl fmt::Display for Pointer {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+impl From<Pointer> for String {
+	fn from(pointer: Pointer) -> Self {
+		pointer.0
+	}
+}
+
+/// Where a pod's metadata and spec are in the admitted object, at its root for pods but nested in pod templates
+#[derive(Debug, Clone, PartialEq)]
+pub struct PodPaths {
+	/// Not every object embedding a pod spec has metadata for it
+	pub metadata: Option<Pointer>,
+	pub spec: Pointer,
+}
+
+impl PodPaths {
+	pub fn root() -> Self {
+		Self::template(Pointer::default())
+	}
+
+	/// Paths for a pod template at `template`, such as `/spec/template` for Deployments
+	pub fn template(template: Pointer) -> Self {
+		Self {
+			metadata: Some(template.join(&["metadata"])),
+			spec: template.join(&["spec"]),
+		}
+	}
+
+	/// Reads the pod, or pod template, out of the admitted object, `None` if it has no valid spec
+	pub fn extract(&self, document: &Value) -> Option<Pod> {
+		let spec = document.pointer(&self.spec.to_string()).and_then(|s| PodSpec::deserialize(s).ok())?;
+		let metadata = self.metadata.as_ref()
+			.and_then(|m| document.pointer(&m.to_string()))
+			.and_then(|m| Deserialize::deserialize(m).ok())
+			.unwrap_or_default();
+
+		Some(Pod { metadata, spec: Some(spec), ..Default::default() })
+	}
+}
+
+fn operation_path(operation: &PatchOperation) -> &str {
+	match operation {
+		PatchOperation::Add(op) => &op.path,
+		PatchOperation::Remove(op) => &op.path,
+		PatchOperation::Replace(op) => &op.path,
+		PatchOperation::Move(op) => &op.path,
+		PatchOperation::Copy(op) => &op.path,
+		PatchOperation::Test(op) => &op.path,
+	}
+}
+
+/// Collapses the patches for the field at `path` into a single operation setting its final value, if that is smaller
+///
+/// Applying either variant to `document` yields the same result. The patches are returned unchanged if any of them
+/// targets something outside of `path`.
+pub fn minimise(document: &Value, path: &Pointer, patches: Vec<PatchOperation>) -> Vec<PatchOperation> {
+	let prefix = path.to_string();
+	let within_path = |op: &PatchOperation| {
+		let op_path = operation_path(op);
+		op_path == prefix || op_path.strip_prefix(&prefix).is_some_and(|rest| rest.starts_with('/'))
+	};
+	if patches.is_empty() || !patches.iter().all(within_path) {
+		return patches;
+	}
+
+	let mut patched = document.clone();
+	if json_patch::patch(&mut patched, &patches).is_err() {
+		return patches;
+	}
+	let Some(value) = patched.pointer(&prefix) else {
+		return patches;
+	};
+
+	let collapsed = match document.pointer(&prefix) {
+		Some(_) => replace(prefix, value.clone()),
+		None => add(prefix, value.clone()),
+	};
+
+	let size = |ops: &[PatchOperation]| serde_json::to_vec(ops).map(|v| v.len()).unwrap_or(usize::MAX);
+	match size(std::slice::from_ref(&collapsed)) < size(&patches) {
+		true => vec![collapsed],
+		false => patches,
+	}
+}
+
+/// A single comparison between pod-director's configuration and the pod, used to explain mutations
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Decision {
+	pub field: &'static str,
+	pub key: Option<String>,
+	pub config_value: Value,
+	pub pod_value: Option<Value>,
+	pub outcome: Outcome,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum Outcome {
+	/// The pod had no value, so the configured one was added
+	Added,
+	/// The pod already had the configured value
+	Unchanged,
+	/// The pod had a different value which was kept due to `Conflict::Ignore`
+	Ignored,
+	/// The pod had a different value which was replaced due to `Conflict::Override`
+	Overridden,
+	/// The pod had a different value and was denied due to `Conflict::Reject`
+	Rejected,
+}
+
+pub enum PatchResult {
 	Allow(Vec<PatchOperation>),
-	Deny {
-		label: &'a str,
-		config_value: &'a str,
-		conflicting_value: &'a str,
-	},
-}
-
-pub fn calculate_node_selector_patches<'a>(
-	pod_spec: &'a PodSpec,
-	node_selector_config: &'a HashMap<String, String>,
-	conflict_config: &'a Conflict,
-) -> PatchResult<'a> {
+	Deny(Denial),
+}
+
+/// A conflict between the pod and pod-director's configuration under `Conflict::Reject`
+#[derive(Debug, PartialEq)]
+pub struct Denial {
+	pub field: &'static str,
+	pub key: Option<String>,
+	pub config_value: String,
+	pub conflicting_value: String,
+}
+
+impl fmt::Display for Denial {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let Self { field, config_value, conflicting_value, .. } = self;
+		match &self.key {
+			Some(key) => write!(
+				f,
+				"The pod's {field} {key}={conflicting_value} conflicts with pod-director's configuration {key}={config_value}",
+			),
+			None => write!(
+				f,
+				"The pod's {field} {conflicting_value} conflicts with pod-director's configuration {config_value}",
+			),
+		}
+	}
+}
+
+/// Patches are generated in key order, so the same pod and configuration always produce the same patches
+pub fn calculate_node_selector_patches(
+	spec_path: &Pointer,
+	pod_spec: &PodSpec,
+	node_selector_config: &BTreeMap<String, String>,
+	conflict_config: &Conflict,
+	decisions: &mut Vec<Decision>,
+) -> PatchResult {
+	calculate_map_patches(
+		"nodeSelector",
+		spec_path.join(&["nodeSelector"]),
+		pod_spec.node_selector.as_ref(),
+		node_selector_config,
+		conflict_config,
+		decisions,
+	)
+}
+
+/// Sets every configured key of a string map in the pod, such as its nodeSelector or labels, creating the map if needed
+///
+/// Keys the pod already sets to another value are handled according to `conflict_config`, with the first rejected key
+/// denying the pod.
+pub fn calculate_map_patches(
+	field: &'static str,
+	path: Pointer,
+	pod_map: Option<&BTreeMap<String, String>>,
+	map_config: &BTreeMap<String, String>,
+	conflict_config: &Conflict,
+	decisions: &mut Vec<Decision>,
+) -> PatchResult {
 	let mut patches = Vec::new();
 
-	let maybe_node_selector = pod_spec.node_selector.as_ref();
+	let mut decide = |k: &String, v: &String, existing_value: Option<&String>, outcome| decisions.push(Decision {
+		field,
+		key: Some(k.clone()),
+		config_value: json!(v),
+		pod_value: existing_value.map(|e| json!(e)),
+		outcome,
+	});
 
-	if let Some(node_selector) = maybe_node_selector {
-		for (k, v) in node_selector_config {
-			match node_selector.get(k) {
-				None => patches.push(add(format!("/spec/nodeSelector/{k}"), json!(v))),
-				Some(existing_value) if existing_value == v => continue,
+	if let Some(map) = pod_map {
+		for (k, v) in map_config {
+			match map.get(k) {
+				None => {
+					decide(k, v, None, Outcome::Added);
+					patches.push(add(path.clone().push(k).into(), json!(v)));
+				}
+				Some(existing_value) if existing_value == v => decide(k, v, Some(existing_value), Outcome::Unchanged),
 				Some(existing_value) => match conflict_config {
-					Conflict::Ignore => (),
-					Conflict::Override => patches.push(replace(format!("/spec/nodeSelector/{k}"), json!(v))),
+					Conflict::Ignore => decide(k, v, Some(existing_value), Outcome::Ignored),
+					Conflict::Override => {
+						decide(k, v, Some(existing_value), Outcome::Overridden);
+						patches.push(replace(path.clone().push(k).into(), json!(v)));
+					}
 					Conflict::Reject => {
-						return PatchResult::Deny {
-							label: k.as_str(),
-							config_value: v.as_str(),
-							conflicting_value: existing_value.as_str(),
-						};
+						decide(k, v, Some(existing_value), Outcome::Rejected);
+						return PatchResult::Deny(Denial {
+							field,
+							key: Some(k.clone()),
+							config_value: v.clone(),
+							conflicting_value: existing_value.clone(),
+						});
 					}
 				},
 			}
 		}
 	} else {
-		patches.push(add("/spec/nodeSelector".into(), json!({})));
-		for (k, v) in node_selector_config {
-			patches.push(add(format!("/spec/nodeSelector/{k}"), json!(v)));
+		patches.push(add(path.clone().into(), json!({})));
+		for (k, v) in map_config {
+			decide(k, v, None, Outcome::Added);
+			patches.push(add(path.clone().push(k).into(), json!(v)));
 		};
 	}
 
 	PatchResult::Allow(patches)
 }
 
-pub fn calculate_toleration_patches(pod_spec: &PodSpec, tolerations_config: &[Toleration]) -> Vec<PatchOperation> {
+/// Patches are generated in the order the tolerations are configured
+pub fn calculate_toleration_patches(
+	spec_path: &Pointer,
+	pod_spec: &PodSpec,
+	tolerations_config: &[Toleration],
+	decisions: &mut Vec<Decision>,
+) -> Vec<PatchOperation> {
 	let mut patches = Vec::new();
 
 	let maybe_tolerations = pod_spec.tolerations.as_ref();
+	let tolerations_path = spec_path.join(&["tolerations"]);
+
+	let mut decide = |t: &Toleration, present: bool| decisions.push(Decision {
+		field: "tolerations",
+		key: t.key.clone(),
+		config_value: json!(t),
+		pod_value: present.then(|| json!(t)),
+		outcome: if present { Outcome::Unchanged } else { Outcome::Added },
+	});
 
 	if let Some(tolerations) = maybe_tolerations {
-		tolerations_config.iter()
-			.filter(|t| !tolerations.contains(t))
-			.for_each(|t| patches.push(
-				add("/spec/tolerations/-".into(), json!(t))
-			));
+		for t in tolerations_config {
+			let present = tolerations.contains(t);
+			decide(t, present);
+			if !present {
+				patches.push(add(tolerations_path.clone().push("-").into(), json!(t)));
+			}
+		}
 	}
 	else {
-		patches.push(add("/spec/tolerations".into(), json!([])));
+		patches.push(add(tolerations_path.clone().into(), json!([])));
 		for t in tolerations_config {
-			patches.push(add("/spec/tolerations/-".into(), json!(t)))
+			decide(t, false);
+			patches.push(add(tolerations_path.clone().push("-").into(), json!(t)))
+		}
+	}
+
+	patches
+}
+
+/// Constraints are only added if the pod has none for the same topology key, otherwise they conflict unless equal
+pub fn calculate_topology_spread_constraint_patches(
+	spec_path: &Pointer,
+	pod_spec: &PodSpec,
+	constraints_config: &[TopologySpreadConstraint],
+	conflict_config: &Conflict,
+	decisions: &mut Vec<Decision>,
+) -> PatchResult {
+	let mut patches = Vec::new();
+
+	let existing_constraints = pod_spec.topology_spread_constraints.as_deref().unwrap_or_default();
+	let constraints_path = spec_path.join(&["topologySpreadConstraints"]);
+
+	let mut decide = |c: &TopologySpreadConstraint, existing: Option<&TopologySpreadConstraint>, outcome| decisions.push(Decision {
+		field: "topologySpreadConstraints",
+		key: Some(c.topology_key.clone()),
+		config_value: json!(c),
+		pod_value: existing.map(|e| json!(e)),
+		outcome,
+	});
+
+	if pod_spec.topology_spread_constraints.is_none() && !constraints_config.is_empty() {
+		patches.push(add(constraints_path.clone().into(), json!([])));
+	}
+
+	for c in constraints_config {
+		match existing_constraints.iter().position(|e| e.topology_key == c.topology_key) {
+			None => {
+				decide(c, None, Outcome::Added);
+				patches.push(add(constraints_path.clone().push("-").into(), json!(c)));
+			}
+			Some(i) if &existing_constraints[i] == c => decide(c, Some(c), Outcome::Unchanged),
+			Some(i) => {
+				let existing = &existing_constraints[i];
+				match conflict_config {
+					Conflict::Ignore => decide(c, Some(existing), Outcome::Ignored),
+					Conflict::Override => {
+						decide(c, Some(existing), Outcome::Overridden);
+						patches.push(replace(constraints_path.clone().push(&i.to_string()).into(), json!(c)));
+					}
+					Conflict::Reject => {
+						decide(c, Some(existing), Outcome::Rejected);
+						return PatchResult::Deny(Denial {
+							field: "topologySpreadConstraints",
+							key: Some(c.topology_key.clone()),
+							config_value: json!(c).to_string(),
+							conflicting_value: json!(existing).to_string(),
+						});
+					}
+				}
+			}
+		}
+	}
+
+	PatchResult::Allow(patches)
+}
+
+/// Sets a scalar field of the pod's spec, `field` being its name in the pod's JSON, such as `schedulerName`
+pub fn calculate_scalar_patch<T: Serialize + PartialEq + fmt::Display>(
+	spec_path: &Pointer,
+	field: &'static str,
+	pod_value: Option<&T>,
+	config_value: &T,
+	conflict_config: &Conflict,
+	decisions: &mut Vec<Decision>,
+) -> PatchResult {
+	let path = spec_path.join(&[field]);
+
+	let mut decide = |outcome| decisions.push(Decision {
+		field,
+		key: None,
+		config_value: json!(config_value),
+		pod_value: pod_value.map(|v| json!(v)),
+		outcome,
+	});
+
+	match pod_value {
+		None => {
+			decide(Outcome::Added);
+			PatchResult::Allow(vec![add(path.into(), json!(config_value))])
+		}
+		Some(existing_value) if existing_value == config_value => {
+			decide(Outcome::Unchanged);
+			PatchResult::Allow(Vec::new())
+		}
+		Some(existing_value) => match conflict_config {
+			Conflict::Ignore => {
+				decide(Outcome::Ignored);
+				PatchResult::Allow(Vec::new())
+			}
+			Conflict::Override => {
+				decide(Outcome::Overridden);
+				PatchResult::Allow(vec![replace(path.into(), json!(config_value))])
+			}
+			Conflict::Reject => {
+				decide(Outcome::Rejected);
+				PatchResult::Deny(Denial {
+					field,
+					key: None,
+					config_value: config_value.to_string(),
+					conflicting_value: existing_value.to_string(),
+				})
+			}
+		},
+	}
+}
+
+/// Sets default requests and limits on every container and init container lacking them, existing values are never changed
+pub fn calculate_resource_patches(
+	spec_path: &Pointer,
+	pod_spec: &PodSpec,
+	resources_config: &ResourcesConfig,
+	decisions: &mut Vec<Decision>,
+) -> Vec<PatchOperation> {
+	let mut patches = Vec::new();
+
+	let no_defaults = BTreeMap::new();
+	let default_requests = resources_config.default_requests.as_ref().unwrap_or(&no_defaults);
+	let default_limits = resources_config.default_limits.as_ref().unwrap_or(&no_defaults);
+
+	let all_containers = [
+		("containers", pod_spec.containers.as_slice()),
+		("initContainers", pod_spec.init_containers.as_deref().unwrap_or_default()),
+	];
+
+	for (kind, containers) in all_containers {
+		for (i, container) in containers.iter().enumerate() {
+			let resources = container.resources.as_ref();
+			let requests = resources.and_then(|r| r.requests.as_ref());
+			let limits = resources.and_then(|r| r.limits.as_ref());
+
+			let mut missing = |field: &str, defaults: &BTreeMap<String, Quantity>, existing: Option<&BTreeMap<String, Quantity>>| {
+				let mut missing = BTreeMap::new();
+				for (resource, default) in defaults {
+					let existing_value = existing.and_then(|e| e.get(resource));
+					decisions.push(Decision {
+						field: "resources",
+						key: Some(format!("{}.{field}.{resource}", container.name)),
+						config_value: json!(default),
+						pod_value: existing_value.map(|e| json!(e)),
+						outcome: match existing_value {
+							None => Outcome::Added,
+							Some(e) if e == default => Outcome::Unchanged,
+							Some(_) => Outcome::Ignored,
+						},
+					});
+
+					if existing_value.is_none() {
+						missing.insert(resource.clone(), default.clone());
+					}
+				}
+				missing
+			};
+
+			let missing_requests = missing("requests", default_requests, requests);
+			let mut missing_limits = missing("limits", default_limits, limits);
+
+			// A limit below the container's own request would make the pod invalid
+			missing_limits.retain(|resource, limit| {
+				let request = requests.and_then(|r| r.get(resource)).and_then(|r| quantity::parse(&r.0));
+				request.zip(quantity::parse(&limit.0)).is_none_or(|(request, limit)| request <= limit)
+			});
+
+			let resources_path = spec_path.join(&[kind, &i.to_string(), "resources"]);
+
+			if resources.is_none() {
+				let mut value = json!({});
+				if !missing_requests.is_empty() {
+					value["requests"] = json!(missing_requests);
+				}
+				if !missing_limits.is_empty() {
+					value["limits"] = json!(missing_limits);
+				}
+				if value != json!({}) {
+					patches.push(add(resources_path.into(), value));
+				}
+				continue;
+			}
+
+			for (field, existing, missing) in [("requests", requests, missing_requests), ("limits", limits, missing_limits)] {
+				if missing.is_empty() {
+					continue;
+				}
+
+				let path = resources_path.clone().push(field);
+				match existing {
+					Some(_) => patches.extend(missing.iter().map(|(resource, q)| add(path.clone().push(resource).into(), json!(q)))),
+					None => patches.push(add(path.into(), json!(missing))),
+				}
+			}
+		}
+	}
+
+	patches
+}
+
+/// Adds the env, volume mounts and volumes of every injection whose container filter matches, skipping entries already present
+pub fn calculate_injection_patches(
+	spec_path: &Pointer,
+	pod_spec: &PodSpec,
+	injections: &[InjectionConfig],
+	decisions: &mut Vec<Decision>,
+) -> Vec<PatchOperation> {
+	let mut patches = Vec::new();
+
+	let applies = |injection: &InjectionConfig, container: &Container| injection.containers.as_ref()
+		.is_none_or(|names| names.contains(&container.name));
+
+	let all_containers = [
+		("containers", pod_spec.containers.as_slice()),
+		("initContainers", pod_spec.init_containers.as_deref().unwrap_or_default()),
+	];
+
+	for (kind, containers) in all_containers {
+		for (i, container) in containers.iter().enumerate() {
+			let container_path = spec_path.join(&[kind, &i.to_string()]);
+			let matching: Vec<&InjectionConfig> = injections.iter().filter(|injection| applies(injection, container)).collect();
+
+			let env: Vec<&EnvVar> = matching.iter().flat_map(|injection| injection.env.iter().flatten()).collect();
+			patches.extend(append_missing(
+				"env",
+				Some(&container.name),
+				container_path.clone().push("env"),
+				container.env.as_deref(),
+				&env,
+				|e| &e.name,
+				decisions,
+			));
+
+			let volume_mounts: Vec<&VolumeMount> = matching.iter().flat_map(|injection| injection.volume_mounts.iter().flatten()).collect();
+			patches.extend(append_missing(
+				"volumeMounts",
+				Some(&container.name),
+				container_path.push("volumeMounts"),
+				container.volume_mounts.as_deref(),
+				&volume_mounts,
+				|m| &m.mount_path,
+				decisions,
+			));
 		}
 	}
 
+	let volumes: Vec<&Volume> = injections.iter()
+		.filter(|injection| all_containers.iter().flat_map(|(_, containers)| containers.iter()).any(|c| applies(injection, c)))
+		.flat_map(|injection| injection.volumes.iter().flatten())
+		.collect();
+	patches.extend(append_missing(
+		"volumes",
+		None,
+		spec_path.join(&["volumes"]),
+		pod_spec.volumes.as_deref(),
+		&volumes,
+		|v| &v.name,
+		decisions,
+	));
+
 	patches
 }
+
+/// Appends the entries of `wanted` missing from the list at `path`, entries being identified by `id`
+///
+/// Only the first of several wanted entries with the same identity is considered.
+fn append_missing<T: Serialize + PartialEq>(
+	field: &'static str,
+	container: Option<&str>,
+	path: Pointer,
+	existing: Option<&[T]>,
+	wanted: &[&T],
+	id: impl Fn(&T) -> &str,
+	decisions: &mut Vec<Decision>,
+) -> Vec<PatchOperation> {
+	let mut missing: Vec<&T> = Vec::new();
+
+	for (i, w) in wanted.iter().enumerate() {
+		if wanted[..i].iter().any(|earlier| id(earlier) == id(w)) {
+			continue;
+		}
+
+		let present = existing.into_iter().flatten().find(|e| id(e) == id(w));
+		decisions.push(Decision {
+			field,
+			key: Some(match container {
+				Some(container) => format!("{container}.{}", id(w)),
+				None => id(w).to_string(),
+			}),
+			config_value: json!(w),
+			pod_value: present.map(|e| json!(e)),
+			outcome: match present {
+				None => Outcome::Added,
+				Some(e) if e == *w => Outcome::Unchanged,
+				Some(_) => Outcome::Ignored,
+			},
+		});
+
+		if present.is_none() {
+			missing.push(w);
+		}
+	}
+
+	match existing {
+		_ if missing.is_empty() => Vec::new(),
+		Some(_) => missing.iter().map(|m| add(path.clone().push("-").into(), json!(m))).collect(),
+		None => vec![add(path.into(), json!(missing))],
+	}
+}
+
+/// Adds pod-director's own annotations, `has_annotations` telling whether the pod has, or was already patched with, annotations
+pub fn calculate_annotation_patches(
+	metadata_path: &Pointer,
+	has_annotations: bool,
+	annotations: &BTreeMap<String, String>,
+) -> Vec<PatchOperation> {
+	if annotations.is_empty() {
+		return Vec::new();
+	}
+
+	let annotations_path = metadata_path.join(&["annotations"]);
+
+	match has_annotations {
+		true => annotations.iter()
+			.map(|(k, v)| add(annotations_path.clone().push(k).into(), json!(v)))
+			.collect(),
+		false => vec![add(annotations_path.into(), json!(annotations))],
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use k8s_openapi::api::core::v1::{PodSpec, Toleration};
+	use proptest::prelude::*;
+	use serde_json::json;
+
+	use crate::config::{Conflict, GroupConfig};
+
+	use super::*;
+
+	fn label_key() -> impl Strategy<Value = String> {
+		"[a-d][a-z0-9./~-]{0,3}"
+	}
+
+	fn label_value() -> impl Strategy<Value = String> {
+		"[a-c]{0,2}"
+	}
+
+	fn toleration() -> impl Strategy<Value = Toleration> {
+		(
+			proptest::option::of(label_key()),
+			proptest::option::of(prop_oneof![Just("Equal".to_string()), Just("Exists".to_string())]),
+			proptest::option::of(label_value()),
+			proptest::option::of(prop_oneof![Just("NoSchedule".to_string()), Just("NoExecute".to_string())]),
+		).prop_map(|(key, operator, value, effect)| Toleration { key, operator, value, effect, ..Default::default() })
+	}
+
+	fn pod_spec() -> impl Strategy<Value = PodSpec> {
+		(
+			proptest::option::of(proptest::collection::btree_map(label_key(), label_value(), 0..4)),
+			proptest::option::of(proptest::collection::vec(toleration(), 0..4)),
+		).prop_map(|(node_selector, tolerations)| PodSpec { node_selector, tolerations, ..Default::default() })
+	}
+
+	fn group_config() -> impl Strategy<Value = GroupConfig> {
+		(
+			proptest::collection::btree_map(label_key(), label_value(), 0..4),
+			proptest::collection::vec(toleration(), 0..4),
+			prop_oneof![Just(Conflict::Ignore), Just(Conflict::Override), Just(Conflict::Reject)],
+		).prop_map(|(node_selector, tolerations, on_conflict)| GroupConfig {
+			node_selector: Some(node_selector),
+			tolerations: Some(tolerations),
+			on_conflict,
+			..Default::default()
+		})
+	}
+
+	#[test]
+	fn when_building_pointer_should_escape_tokens() {
+		assert_eq!(Pointer::new(&["spec", "nodeSelector"]).to_string(), "/spec/nodeSelector");
+		assert_eq!(Pointer::new(&["spec", "nodeSelector", "kubernetes.io/os"]).to_string(), "/spec/nodeSelector/kubernetes.io~1os");
+		assert_eq!(Pointer::new(&["metadata", "annotations", "a~b/c~1"]).to_string(), "/metadata/annotations/a~0b~1c~01");
+		assert_eq!(Pointer::new(&["spec", "tolerations"]).push("-").to_string(), "/spec/tolerations/-");
+		assert_eq!(Pointer::new(&[]).to_string(), "");
+		assert_eq!(Pointer::new(&["spec", "template"]).join(&["spec", "nodeSelector"]).to_string(), "/spec/template/spec/nodeSelector");
+	}
+
+	#[test]
+	fn when_building_template_paths_should_nest_metadata_and_spec() {
+		assert_eq!(PodPaths::root(), PodPaths { metadata: Some(Pointer::new(&["metadata"])), spec: Pointer::new(&["spec"]) });
+		assert_eq!(PodPaths::template(Pointer::new(&["spec", "jobTemplate", "spec", "template"])).spec.to_string(), "/spec/jobTemplate/spec/template/spec");
+	}
+
+	#[test]
+	fn when_extracting_pod_template_should_read_metadata_and_spec() {
+		let document = json!({
+			"kind": "Deployment",
+			"spec": {"template": {
+				"metadata": {"labels": {"app": "test"}},
+				"spec": {"containers": [{"name": "test", "image": "alpine"}]},
+			}},
+		});
+
+		let pod = PodPaths::template(Pointer::new(&["spec", "template"])).extract(&document).unwrap();
+
+		assert_eq!(pod.metadata.labels, Some(BTreeMap::from([("app".to_string(), "test".to_string())])));
+		assert_eq!(pod.spec.unwrap().containers[0].name, "test");
+		assert_eq!(PodPaths::root().extract(&json!({"metadata": {"name": "test"}})), None);
+	}
+
+	#[test]
+	fn when_parsing_pointer_should_unescape_tokens() {
+		assert_eq!(Pointer::parse("/spec/nodeSelector/kubernetes.io~1os"), Some(vec![
+			"spec".to_string(), "nodeSelector".to_string(), "kubernetes.io/os".to_string(),
+		]));
+		assert_eq!(Pointer::parse("/a~0b~1c~01"), Some(vec!["a~b/c~1".to_string()]));
+		assert_eq!(Pointer::parse(""), Some(vec![]));
+	}
+
+	#[test]
+	fn when_reading_escaped_pointer_should_keep_escapes() {
+		assert_eq!(Pointer::from_escaped("/spec/a~1b").unwrap().to_string(), "/spec/a~1b");
+		assert_eq!(Pointer::from_escaped("/spec/a~1b"), Some(Pointer::new(&["spec", "a/b"])));
+		assert_eq!(Pointer::from_escaped("spec"), None);
+	}
+
+	#[test]
+	fn when_parsing_invalid_pointer_should_return_none() {
+		assert_eq!(Pointer::parse("spec"), None);
+		assert_eq!(Pointer::parse("/a~2"), None);
+		assert_eq!(Pointer::parse("/a~"), None);
+	}
+
+	#[test]
+	fn when_node_selector_key_contains_slash_should_escape_path() {
+		let config = BTreeMap::from([("kubernetes.io/os".to_string(), "linux".to_string())]);
+		let pod_spec = PodSpec {
+			node_selector: Some(BTreeMap::from([("node.kubernetes.io/instance-type".to_string(), "large".to_string())])),
+			..Default::default()
+		};
+
+		let patches = match calculate_node_selector_patches(&PodPaths::root().spec, &pod_spec, &config, &Conflict::Reject, &mut Vec::new()) {
+			PatchResult::Allow(patches) => patches,
+			PatchResult::Deny(_) => panic!("should not deny"),
+		};
+
+		assert_eq!(patches, vec![add("/spec/nodeSelector/kubernetes.io~1os".into(), json!("linux"))]);
+		assert_eq!(apply(&pod_spec, &patches).node_selector, Some(BTreeMap::from([
+			("kubernetes.io/os".to_string(), "linux".to_string()),
+			("node.kubernetes.io/instance-type".to_string(), "large".to_string()),
+		])));
+	}
+
+	#[test]
+	fn when_pod_has_no_node_selector_should_collapse_into_single_add() {
+		let document = json!({"spec": {}});
+		let patches = vec![
+			add("/spec/nodeSelector".into(), json!({})),
+			add("/spec/nodeSelector/label-0".into(), json!("value-0")),
+			add("/spec/nodeSelector/label-1".into(), json!("value-1")),
+		];
+
+		let minimised = minimise(&document, &Pointer::new(&["spec", "nodeSelector"]), patches);
+
+		assert_eq!(minimised, vec![add("/spec/nodeSelector".into(), json!({"label-0": "value-0", "label-1": "value-1"}))]);
+	}
+
+	#[test]
+	fn when_replacing_whole_field_is_smaller_should_collapse_into_single_replace() {
+		let document = json!({"spec": {"nodeSelector": {"a": "1"}}});
+		let patches = vec![
+			replace("/spec/nodeSelector/a".into(), json!("2")),
+			add("/spec/nodeSelector/label-0".into(), json!("value-0")),
+		];
+
+		let minimised = minimise(&document, &Pointer::new(&["spec", "nodeSelector"]), patches);
+
+		assert_eq!(minimised, vec![replace("/spec/nodeSelector".into(), json!({"a": "2", "label-0": "value-0"}))]);
+	}
+
+	#[test]
+	fn when_per_item_patches_are_smaller_should_keep_them() {
+		let tolerations: Vec<Value> = (0..10).map(|i| json!({"key": format!("key-{i}"), "operator": "Exists"})).collect();
+		let document = json!({"spec": {"tolerations": tolerations}});
+		let patches = vec![add("/spec/tolerations/-".into(), json!({"key": "new", "operator": "Exists"}))];
+
+		let minimised = minimise(&document, &Pointer::new(&["spec", "tolerations"]), patches.clone());
+
+		assert_eq!(minimised, patches);
+	}
+
+	#[test]
+	fn when_patches_target_other_fields_should_keep_them() {
+		let document = json!({"spec": {}, "metadata": {}});
+		let patches = vec![
+			add("/spec/nodeSelector".into(), json!({})),
+			add("/spec/nodeSelector/label-0".into(), json!("value-0")),
+			add("/spec/nodeSelectorExtra".into(), json!("value")),
+		];
+
+		let minimised = minimise(&document, &Pointer::new(&["spec", "nodeSelector"]), patches.clone());
+
+		assert_eq!(minimised, patches);
+	}
+
+	fn apply(pod_spec: &PodSpec, patches: &[PatchOperation]) -> PodSpec {
+		let mut pod = json!({"spec": pod_spec});
+		json_patch::patch(&mut pod, patches).expect("patches should apply to the pod they were calculated for");
+		serde_json::from_value(pod["spec"].clone()).unwrap()
+	}
+
+	proptest! {
+		#[test]
+		fn applying_node_selector_patches_yields_configured_labels(pod_spec in pod_spec(), group_config in group_config()) {
+			let node_selector_config: &BTreeMap<String, String> = group_config.node_selector.as_ref().unwrap();
+			let original = pod_spec.node_selector.clone().unwrap_or_default();
+
+			let patches = match calculate_node_selector_patches(&PodPaths::root().spec, &pod_spec, node_selector_config, &group_config.on_conflict, &mut Vec::new()) {
+				PatchResult::Allow(patches) => patches,
+				PatchResult::Deny(denial) => {
+					prop_assert_eq!(&group_config.on_conflict, &Conflict::Reject);
+					prop_assert_eq!(original.get(denial.key.as_ref().unwrap()), Some(&denial.conflicting_value));
+					prop_assert_ne!(denial.config_value, denial.conflicting_value);
+					return Ok(());
+				}
+			};
+
+			let result: BTreeMap<String, String> = apply(&pod_spec, &patches).node_selector.unwrap_or_default();
+			for (k, v) in node_selector_config {
+				let expected = match (original.get(k), &group_config.on_conflict) {
+					(Some(existing), Conflict::Ignore) => existing,
+					_ => v,
+				};
+				prop_assert_eq!(result.get(k), Some(expected));
+			}
+			for (k, v) in &original {
+				if !node_selector_config.contains_key(k) {
+					prop_assert_eq!(result.get(k), Some(v));
+				}
+			}
+		}
+
+		#[test]
+		fn minimised_patches_yield_same_pod(pod_spec in pod_spec(), group_config in group_config()) {
+			let document = json!({"spec": pod_spec});
+			let mut patches = match calculate_node_selector_patches(&PodPaths::root().spec, &pod_spec, group_config.node_selector.as_ref().unwrap(), &group_config.on_conflict, &mut Vec::new()) {
+				PatchResult::Allow(patches) => patches,
+				PatchResult::Deny(_) => Vec::new(),
+			};
+			let toleration_patches = calculate_toleration_patches(&PodPaths::root().spec, &pod_spec, group_config.tolerations.as_ref().unwrap(), &mut Vec::new());
+
+			let mut minimised = minimise(&document, &Pointer::new(&["spec", "nodeSelector"]), patches.clone());
+			minimised.extend(minimise(&document, &Pointer::new(&["spec", "tolerations"]), toleration_patches.clone()));
+			patches.extend(toleration_patches);
+
+			prop_assert_eq!(apply(&pod_spec, &minimised), apply(&pod_spec, &patches));
+			prop_assert!(serde_json::to_vec(&minimised).unwrap().len() <= serde_json::to_vec(&patches).unwrap().len());
+		}
+
+		#[test]
+		fn applying_toleration_patches_yields_configured_tolerations(pod_spec in pod_spec(), group_config in group_config()) {
+			let tolerations_config = group_config.tolerations.as_ref().unwrap();
+			let original = pod_spec.tolerations.clone().unwrap_or_default();
+
+			let patches = calculate_toleration_patches(&PodPaths::root().spec, &pod_spec, tolerations_config, &mut Vec::new());
+
+			let result = apply(&pod_spec, &patches).tolerations.unwrap_or_default();
+			for t in tolerations_config.iter().chain(&original) {
+				prop_assert!(result.contains(t), "{t:?} missing from {result:?}");
+			}
+		}
+	}
+}
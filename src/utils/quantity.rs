@@ -0,0 +1,96 @@
+/// Parses a Kubernetes resource quantity, such as `100m`, `1.5Gi` or `1e3`, into its value in base units
+pub fn parse(quantity: &str) -> Option<f64> {
+	let (number, suffix) = split(quantity);
+
+	let number: f64 = number.parse().ok()?;
+
+	Some(number * multiplier(suffix)?)
+}
+
+/// Formats a value in base units with the same suffix as `reference`, such as `128Mi` for a `100Mi` reference
+///
+/// Falls back to base units if the reference's suffix is unknown.
+pub fn format_like(value: f64, reference: &str) -> String {
+	let (_, suffix) = split(reference);
+
+	match multiplier(suffix) {
+		Some(multiplier) => format!("{}{suffix}", round(value / multiplier)),
+		None => round(value).to_string(),
+	}
+}
+
+fn split(quantity: &str) -> (&str, &str) {
+	let suffix_start = quantity
+		.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '+' || c == '-'))
+		.unwrap_or(quantity.len());
+	quantity.split_at(suffix_start)
+}
+
+fn multiplier(suffix: &str) -> Option<f64> {
+	let multiplier = match suffix {
+		"" => 1.0,
+		"n" => 1e-9,
+		"u" => 1e-6,
+		"m" => 1e-3,
+		"k" => 1e3,
+		"M" => 1e6,
+		"G" => 1e9,
+		"T" => 1e12,
+		"P" => 1e15,
+		"E" => 1e18,
+		"Ki" => 1024f64,
+		"Mi" => 1024f64.powi(2),
+		"Gi" => 1024f64.powi(3),
+		"Ti" => 1024f64.powi(4),
+		"Pi" => 1024f64.powi(5),
+		"Ei" => 1024f64.powi(6),
+		exponent if exponent.starts_with(['e', 'E']) => 10f64.powi(exponent[1..].parse().ok()?),
+		_ => return None,
+	};
+
+	Some(multiplier)
+}
+
+/// Rounds to three decimals, hiding floating point noise such as `0.30000000000000004`
+fn round(value: f64) -> f64 {
+	(value * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{format_like, parse};
+
+	#[test]
+	fn given_valid_quantities_should_parse_them() {
+		assert_eq!(parse("1"), Some(1.0));
+		assert_eq!(parse("0.5"), Some(0.5));
+		assert_eq!(parse("100m"), Some(0.1));
+		assert_eq!(parse("2k"), Some(2000.0));
+		assert_eq!(parse("1Ki"), Some(1024.0));
+		assert_eq!(parse("1.5Gi"), Some(1.5 * 1024.0 * 1024.0 * 1024.0));
+		assert_eq!(parse("128Mi"), Some(128.0 * 1024.0 * 1024.0));
+		assert_eq!(parse("1e3"), Some(1000.0));
+		assert_eq!(parse("1E3"), Some(1000.0));
+		assert_eq!(parse("2E"), Some(2e18));
+	}
+
+	#[test]
+	fn given_invalid_quantities_should_reject_them() {
+		assert_eq!(parse(""), None);
+		assert_eq!(parse("."), None);
+		assert_eq!(parse("Mi"), None);
+		assert_eq!(parse("1mi"), None);
+		assert_eq!(parse("1 Gi"), None);
+		assert_eq!(parse("one"), None);
+		assert_eq!(parse("1e"), None);
+	}
+
+	#[test]
+	fn given_reference_quantity_should_format_with_its_suffix() {
+		assert_eq!(format_like(128.0 * 1024.0 * 1024.0, "100Mi"), "128Mi");
+		assert_eq!(format_like(1.5, "1"), "1.5");
+		assert_eq!(format_like(0.1 + 0.2, "500m"), "300m");
+		assert_eq!(format_like(2500.0, "2e3"), "2.5e3");
+		assert_eq!(format_like(0.1 + 0.2, "1"), "0.3");
+	}
+}